@@ -0,0 +1,90 @@
+//! Benchmarks of the KEM operations
+//!
+//! Run with `cargo bench`, and with `--all-features` to include the APIs
+//! that need an allocator.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use kyber::kem::{kyber768, kyber768_90s, ml_kem_1024, ml_kem_512, ml_kem_768};
+use rand::thread_rng;
+
+// Number of keys or ciphertexts generated per iteration of the batch benchmarks
+const BATCH: usize = 16;
+
+macro_rules! bench_kem {
+    ($c: expr, $module: ident) => {{
+        let mut group = $c.benchmark_group(stringify!($module));
+        let mut rng = thread_rng();
+        let (pk, sk) = $module::keypair(&mut rng).unwrap();
+        let (ct, _) = $module::encaps(&pk, &mut rng).unwrap();
+
+        group.bench_function("keypair", |b| {
+            b.iter(|| $module::keypair(&mut rng).unwrap())
+        });
+        group.bench_function("encaps", |b| {
+            b.iter(|| $module::encaps(&pk, &mut rng).unwrap())
+        });
+        group.bench_function("decaps", |b| b.iter(|| $module::decaps(&ct, &sk)));
+
+        let pk = $module::ExpandedPublicKey::new(&pk);
+        let sk = $module::ExpandedSecretKey::new(&sk);
+        group.bench_function("encaps_expanded", |b| {
+            b.iter(|| $module::encaps_expanded(&pk, &mut rng).unwrap())
+        });
+        group.bench_function("decaps_expanded", |b| {
+            b.iter(|| $module::decaps_expanded(&ct, &sk))
+        });
+        group.finish();
+    }};
+}
+
+fn kem(c: &mut Criterion) {
+    bench_kem!(c, ml_kem_512);
+    bench_kem!(c, ml_kem_768);
+    bench_kem!(c, ml_kem_1024);
+    bench_kem!(c, kyber768);
+    bench_kem!(c, kyber768_90s);
+}
+
+macro_rules! bench_batch {
+    ($c: expr, $module: ident) => {{
+        let mut group = $c.benchmark_group(concat!(stringify!($module), "_batch"));
+        group.throughput(Throughput::Elements(BATCH as u64));
+        let mut rng = thread_rng();
+        let mut keys: [Option<_>; BATCH] = Default::default();
+
+        group.bench_function("keypair loop", |b| {
+            b.iter(|| {
+                for key in &mut keys {
+                    *key = Some($module::keypair(&mut rng).unwrap());
+                }
+            })
+        });
+        group.bench_function("keypair_batch", |b| {
+            b.iter(|| $module::keypair_batch(&mut rng, &mut keys).unwrap())
+        });
+
+        let (pk, _) = $module::keypair(&mut rng).unwrap();
+        group.bench_function("encaps loop", |b| {
+            b.iter(|| {
+                for _ in 0..BATCH {
+                    std::hint::black_box($module::encaps(&pk, &mut rng).unwrap());
+                }
+            })
+        });
+        #[cfg(feature = "alloc")]
+        group.bench_function("encaps_batch", |b| {
+            b.iter(|| $module::encaps_batch(&pk, &mut rng, BATCH).unwrap())
+        });
+        group.finish();
+    }};
+}
+
+fn batch(c: &mut Criterion) {
+    bench_batch!(c, ml_kem_512);
+    bench_batch!(c, ml_kem_768);
+    bench_batch!(c, ml_kem_1024);
+    bench_batch!(c, kyber768_90s);
+}
+
+criterion_group!(benches, kem, batch);
+criterion_main!(benches);
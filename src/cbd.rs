@@ -1,29 +1,139 @@
-use crate::{params::*, poly::Poly};
-
+use hybrid_array::Array;
 
+use crate::{params::*, poly::Poly};
 
-pub fn cbd2<const K: usize>(input: &[u8; kyber_eta1::<K>()*KYBER_N/4]) -> Poly<K> {
-    let mut poly = Poly::<K>::zero();
+/// Sample a polynomial from the centered binomial distribution with eta = 2
+pub fn cbd2<P: ParameterSet>(input: &[u8; 2 * KYBER_N / 4]) -> Poly<P> {
+    let mut poly = Poly::<P>::zero();
 
-    for i in 0..KYBER_N/8 {
-        let t = u32::from_le_bytes(input[4*i..4*i+4].try_into().unwrap());
+    for i in 0..KYBER_N / 8 {
+        let t = u32::from_le_bytes(input[4 * i..4 * i + 4].try_into().unwrap());
         let mut d = t & 0x5555_5555;
         d += (t >> 1) & 0x5555_5555;
 
         for j in 0..8 {
             let a = (d >> (4 * j + 0)) & 0x3;
-            let b = (d >> (4*j +2)) & 0x3;
-            poly.coeffs[8*i+j] = (a as i16) - (b as i16);
+            let b = (d >> (4 * j + 2)) & 0x3;
+            poly.coeffs[8 * i + j] = (a as i16) - (b as i16);
         }
     }
     poly
 }
 
+/// Sample a polynomial from the centered binomial distribution with eta = 3
+///
+/// Reads the input in 24-bit windows, each of which gives four coefficients.
+pub fn cbd3<P: ParameterSet>(input: &[u8; 3 * KYBER_N / 4]) -> Poly<P> {
+    let mut poly = Poly::<P>::zero();
+
+    for i in 0..KYBER_N / 4 {
+        let t = u32::from_le_bytes([input[3 * i], input[3 * i + 1], input[3 * i + 2], 0]);
+        let mut d = t & 0x0024_9249;
+        d += (t >> 1) & 0x0024_9249;
+        d += (t >> 2) & 0x0024_9249;
+
+        for j in 0..4 {
+            let a = (d >> (6 * j + 0)) & 0x7;
+            let b = (d >> (6 * j + 3)) & 0x7;
+            poly.coeffs[4 * i + j] = (a as i16) - (b as i16);
+        }
+    }
+    poly
+}
 
-pub fn poly_cbd_eta1<const K: usize>(input: &[u8; kyber_eta1::<K>()*KYBER_N/4]) -> Poly<K> {
-    if K == 2 {
-        todo!("implement cbd3")
+pub fn poly_cbd_eta1<P: ParameterSet>(input: &Array<u8, P::Eta1RandomnessBytes>) -> Poly<P> {
+    if P::ETA1 == 3 {
+        cbd3(input[..].try_into().unwrap())
     } else {
-        cbd2(input)
+        cbd2(input[..].try_into().unwrap())
+    }
+}
+
+pub fn poly_cbd_eta2<P: ParameterSet>(input: &Array<u8, P::Eta2RandomnessBytes>) -> Poly<P> {
+    cbd2(input[..].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::random_array;
+
+    /// Deterministic input bytes for the known-answer tests
+    fn test_input<const N: usize>() -> [u8; N] {
+        let mut input = [0u8; N];
+        for (i, byte) in input.iter_mut().enumerate() {
+            *byte = (i * 7 + 3) as u8;
+        }
+        input
     }
-}
\ No newline at end of file
+
+    /// Count the occurrences of each value in `[-eta, eta]` over many samples
+    /// and compare them to the binomial distribution.
+    fn check_distribution(eta: usize, sample: impl Fn() -> Poly<Params768>) {
+        const SAMPLES: usize = 1000;
+        let mut counts = [0usize; 7];
+
+        for _ in 0..SAMPLES {
+            for &c in sample().coeffs.iter() {
+                assert!(c.unsigned_abs() as usize <= eta, "{} out of range", c);
+                counts[(c + 3) as usize] += 1;
+            }
+        }
+
+        let total = (SAMPLES * KYBER_N) as f64;
+        let binomial =
+            |n: usize, k: usize| (0..k).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64);
+        for (value, &count) in counts.iter().enumerate() {
+            let k = value as isize - 3;
+            let expected = if k.unsigned_abs() <= eta {
+                binomial(2 * eta, (eta as isize + k) as usize) / (1 << (2 * eta)) as f64
+            } else {
+                0.0
+            };
+            let observed = count as f64 / total;
+            assert!(
+                (observed - expected).abs() < 0.005,
+                "P({}) = {} but expected {}",
+                k,
+                observed,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_cbd2_distribution() {
+        check_distribution(2, || cbd2(&random_array()));
+    }
+
+    #[test]
+    fn test_cbd3_distribution() {
+        check_distribution(3, || cbd3(&random_array()));
+    }
+
+    #[test]
+    fn test_cbd2_known_answer() {
+        let poly = cbd2::<Params768>(&test_input());
+        assert_eq!(
+            poly.coeffs[..32],
+            [
+                2, 0, 0, 0, 1, 1, -1, 1, 0, 1, 0, 1, -1, 1, -1, 2, 1, 2, 1, -1, 0, -1, 0, 0, 1, 0,
+                -1, 0, 0, 0, -2, 0
+            ]
+        );
+        assert_eq!(poly.coeffs.iter().sum::<i16>(), 8);
+    }
+
+    #[test]
+    fn test_cbd3_known_answer() {
+        let poly = cbd3::<Params512>(&test_input());
+        assert_eq!(
+            poly.coeffs[..32],
+            [
+                2, -2, -1, 1, -2, -2, 0, 0, 0, -1, 0, 1, 1, 1, 1, 0, 2, -2, 1, -1, -1, 1, 2, 0, 1,
+                0, -3, 1, 1, 0, 0, -1
+            ]
+        );
+        assert_eq!(poly.coeffs.iter().sum::<i16>(), 0);
+    }
+}
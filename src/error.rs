@@ -0,0 +1,46 @@
+//! Errors returned by this crate
+
+use core::fmt;
+
+/// Errors that can occur when using the KEM or parsing its inputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// A key does not have the length required by the parameter set
+    InvalidKeyLength,
+    /// The encapsulation key contains coefficients that are not reduced
+    /// modulo q (the FIPS 203 modulus check)
+    EncapsulationKeyModulusCheck,
+    /// The hash of the public key embedded in the decapsulation key does
+    /// not match the stored hash (the FIPS 203 hash check)
+    DecapsulationKeyHashMismatch,
+    /// A ciphertext does not have the length required by the parameter set
+    InvalidCiphertextLength,
+    /// A shared secret is not 32 bytes long
+    InvalidSharedSecretLength,
+    /// A key or ciphertext belongs to a different parameter set than the
+    /// other inputs of the operation
+    VariantMismatch,
+    /// The random number generator failed to provide randomness
+    RngFailure,
+    /// A freshly generated key pair failed the pairwise consistency test
+    PairwiseConsistency,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            Error::InvalidKeyLength => "invalid key length",
+            Error::EncapsulationKeyModulusCheck => "encapsulation key failed the modulus check",
+            Error::DecapsulationKeyHashMismatch => "decapsulation key failed the hash check",
+            Error::InvalidCiphertextLength => "invalid ciphertext length",
+            Error::InvalidSharedSecretLength => "invalid shared secret length",
+            Error::VariantMismatch => "mismatched parameter sets",
+            Error::RngFailure => "random number generator failure",
+            Error::PairwiseConsistency => "key pair failed the pairwise consistency test",
+        };
+        f.write_str(msg)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
@@ -1,18 +1,21 @@
-use std::mem::MaybeUninit;
+use hybrid_array::Array;
 
-use crate::{params::*, poly::Poly, polyvec::PolyVec, symmetric::*, utils::split_array};
+use crate::{
+    params::*,
+    poly::Poly,
+    polyvec::PolyVec,
+    symmetric::*,
+    utils::{split_array, wipe},
+};
 
 /// Serialize the public key by concatinating the
 /// polynomial vector pk with the seed for matrix A.
-fn pack_pk<const K: usize>(
-    pk: &PolyVec<K>,
+fn pack_pk<P: ParameterSet>(
+    pk: &PolyVec<P>,
     seed: &[u8; KYBER_SYMBYTES],
-    output: &mut [u8; kyber_indcpa_pkbytes::<K>()], // https://hackmd.io/OZG_XiLFRs2Xmw5s39jRzA?view
-) where
-    [(); kyber_polyvec_bytes::<K>()]: , // huh?
-{
-    // FIXME once https://github.com/rust-lang/rust/issues/74674 clarifies
-    let (polypart, seedpart) = output.split_at_mut(kyber_polyvec_bytes::<K>());
+    output: &mut Array<u8, P::IndcpaPkBytes>,
+) {
+    let (polypart, seedpart) = output.split_at_mut(P::POLYVEC_BYTES);
     let polypart = polypart.try_into().unwrap();
     pk.to_bytes(polypart);
 
@@ -20,27 +23,35 @@ fn pack_pk<const K: usize>(
     seedpart.copy_from_slice(seed);
 }
 
-fn unpack_pk<const K: usize>(
-    packed_pk: &[u8; kyber_indcpa_pkbytes::<K>()],
-) -> (PolyVec<K>, [u8; KYBER_SYMBYTES])
-where
-    [(); kyber_polyvec_bytes::<K>()]: ,
-{
-    // FIXME once https://github.com/rust-lang/rust/issues/74674 clarifies
-    let mut seed = MaybeUninit::uninit_array();
-    let (polypart, seedpart) = packed_pk.split_at(kyber_polyvec_bytes::<K>());
+fn unpack_pk<P: ParameterSet>(
+    packed_pk: &Array<u8, P::IndcpaPkBytes>,
+) -> (PolyVec<P>, [u8; KYBER_SYMBYTES]) {
+    let (polypart, seedpart) = packed_pk.split_at(P::POLYVEC_BYTES);
 
     // deserialize pk
-    let poly = PolyVec::<K>::from_bytes(polypart.try_into().unwrap());
+    let poly = PolyVec::<P>::from_bytes(polypart.try_into().unwrap());
 
-    // initialize seed for matrix A
-    MaybeUninit::write_slice(&mut seed, seedpart);
-
-    let seed = unsafe { MaybeUninit::array_assume_init(seed) };
+    // seed for matrix A
+    let seed = seedpart.try_into().unwrap();
 
     (poly, seed)
 }
 
+/// Check that the polynomial vector of a serialized public key is encoded
+/// canonically, i.e. that all coefficients are reduced modulo q
+///
+/// This is the modulus check `ByteEncode12(ByteDecode12(ek)) == ek` of FIPS 203.
+pub(crate) fn check_pk<P: ParameterSet>(packed_pk: &Array<u8, P::IndcpaPkBytes>) -> bool {
+    let (polypart, _) = packed_pk.split_at(P::POLYVEC_BYTES);
+
+    let mut pkpv = PolyVec::<P>::from_bytes(polypart.try_into().unwrap());
+    pkpv.reduce();
+    let mut encoded = Array::<u8, P::PolyVecBytes>::default();
+    pkpv.to_bytes(&mut encoded);
+
+    encoded[..] == polypart[..]
+}
+
 /// Run rejection sampling on uniform random bytes to generate
 /// uniform random integers mod q.
 ///
@@ -54,9 +65,9 @@ fn rej_uniform(input: &[u8], r: &mut [i16]) -> usize {
     let mut ctr = 0;
     let mut pos = 0;
 
-    while ctr < r.len() && pos + 3 >= input.len() {
-        let val0 = ((input[pos+0] >> 0) as i16 | ((input[pos+1] as i16) << 8)) & 0xFFF;
-        let val1 = ((input[pos+1] >> 4) as i16 | ((input[pos+2] as i16) << 4)) & 0xFFF;
+    while ctr < r.len() && pos + 3 <= input.len() {
+        let val0 = ((input[pos + 0] >> 0) as i16 | ((input[pos + 1] as i16) << 8)) & 0xFFF;
+        let val1 = ((input[pos + 1] >> 4) as i16 | ((input[pos + 2] as i16) << 4)) & 0xFFF;
         pos += 3;
 
         if val0 < KYBER_Q as i16 {
@@ -72,100 +83,442 @@ fn rej_uniform(input: &[u8], r: &mut [i16]) -> usize {
     ctr
 }
 
+// The SHAKE128 rate. Both XOFs are byte streams, so this only sets how much
+// output is buffered at a time and works for AES-256-CTR as well.
 const XOF_BLOCKBYTES: usize = 168;
 
 const GEN_MATRIX_NBLOCKS: usize =
     (12 * KYBER_N / 8 * (1 << 12) / KYBER_Q + XOF_BLOCKBYTES) / XOF_BLOCKBYTES;
 
-fn gen_matrix<const K: usize>(seed: &[u8; KYBER_SYMBYTES], transposed: bool) -> [PolyVec<K>; K] {
+/// Sample the entry in row `i` and column `j` of the matrix A, or of its
+/// transpose, in the NTT domain
+fn gen_matrix_entry<P: ParameterSet, S: Symmetric>(
+    seed: &[u8; KYBER_SYMBYTES],
+    i: usize,
+    j: usize,
+    transposed: bool,
+) -> Poly<P> {
     let mut buffer = [0u8; GEN_MATRIX_NBLOCKS * XOF_BLOCKBYTES + 2];
-    let mut polys: [MaybeUninit<PolyVec<K>>; K] = MaybeUninit::uninit_array();
-    for (i, polyvec) in polys.iter_mut().enumerate() {
-        let mut new_polyvec = PolyVec::<K>::new();
-        for (j, poly) in new_polyvec.vec.iter_mut().enumerate() {
-            let mut xof = XofState::new();
-            if transposed {
-                xof.absorb(seed, i as u8, j as u8);
-            } else {
-                xof.absorb(seed, j as u8, i as u8);
-            }
+    let mut poly = Poly::<P>::zero();
+    let mut xof = if transposed {
+        S::xof(seed, i as u8, j as u8)
+    } else {
+        S::xof(seed, j as u8, i as u8)
+    };
+
+    let mut buflen = GEN_MATRIX_NBLOCKS * XOF_BLOCKBYTES;
+    xof.squeeze(&mut buffer[..buflen]);
+    let mut sampled = rej_uniform(&buffer[..buflen], &mut poly.coeffs[..]);
+    while sampled < KYBER_N {
+        let offset = buflen % 3;
+        for k in 0..offset {
+            buffer[k] = buffer[buflen - offset + k];
+        }
+        xof.squeeze(&mut buffer[offset..offset + XOF_BLOCKBYTES]);
+        buflen = offset + XOF_BLOCKBYTES;
+        sampled += rej_uniform(&buffer[0..buflen], &mut poly.coeffs[sampled..]);
+    }
+    poly
+}
+
+/// Sample the entry in row `i` and column `j` of the matrices A of four
+/// seeds, see [`gen_matrix_entry`]
+///
+/// The four XOFs are squeezed together until every polynomial is complete.
+fn gen_matrix_entry_x4<P: ParameterSet, S: Symmetric>(
+    seeds: [&[u8; KYBER_SYMBYTES]; 4],
+    i: usize,
+    j: usize,
+) -> [Poly<P>; 4] {
+    let mut buffers = [[0u8; GEN_MATRIX_NBLOCKS * XOF_BLOCKBYTES + 2]; 4];
+    let mut polys = core::array::from_fn(|_| Poly::<P>::zero());
+    let mut xof = S::xof_x4(seeds, j as u8, i as u8);
 
-            let mut buflen = GEN_MATRIX_NBLOCKS * XOF_BLOCKBYTES;
-            xof.squeeze(&mut buffer[..buflen]);
-            let mut sampled = rej_uniform(&buffer[..], &mut poly.coeffs[..]);
-            while sampled < KYBER_N {
-                let offset = buflen % 3;
-                for k in 0..offset {
-                    buffer[k] = buffer[buflen - offset + k];
-                }
-                xof.squeeze(&mut buffer[offset..XOF_BLOCKBYTES]);
-                buflen = offset + XOF_BLOCKBYTES;
-                sampled += rej_uniform(&buffer[0..buflen], &mut poly.coeffs[sampled..]);
+    let mut buflen = GEN_MATRIX_NBLOCKS * XOF_BLOCKBYTES;
+    xof.squeeze(buffers.each_mut().map(|buffer| &mut buffer[..buflen]));
+    let mut sampled = [0; 4];
+    for ((count, poly), buffer) in sampled.iter_mut().zip(&mut polys).zip(&buffers) {
+        *count = rej_uniform(&buffer[..buflen], &mut poly.coeffs[..]);
+    }
+    while sampled.iter().any(|&count| count < KYBER_N) {
+        let offset = buflen % 3;
+        for buffer in &mut buffers {
+            buffer.copy_within(buflen - offset..buflen, 0);
+        }
+        xof.squeeze(
+            buffers
+                .each_mut()
+                .map(|buffer| &mut buffer[offset..offset + XOF_BLOCKBYTES]),
+        );
+        buflen = offset + XOF_BLOCKBYTES;
+        for ((count, poly), buffer) in sampled.iter_mut().zip(&mut polys).zip(&buffers) {
+            *count += rej_uniform(&buffer[..buflen], &mut poly.coeffs[*count..]);
+        }
+    }
+    polys
+}
+
+/// Multiply the matrix A, or its transpose, by a vector in the NTT domain
+///
+/// The matrix is sampled one entry at a time instead of being held in
+/// memory, so only a single polynomial of it is on the stack at any time.
+fn matrix_vec_mul<P: ParameterSet, S: Symmetric>(
+    seed: &[u8; KYBER_SYMBYTES],
+    transposed: bool,
+    vec: &PolyVec<P>,
+) -> PolyVec<P> {
+    let mut out = PolyVec::<P>::new();
+    for (i, poly) in out.vec.iter_mut().enumerate() {
+        for (j, b) in vec.vec.iter().enumerate() {
+            *poly += gen_matrix_entry::<P, S>(seed, i, j, transposed).basemul_montgomery(b);
+        }
+        poly.reduce();
+    }
+    out
+}
+
+/// Public key of the CPA-secure scheme with the vector t and the matrix A^T
+/// unpacked, so that they don't have to be recomputed for every encryption
+pub(crate) struct IndcpaExpandedPublicKey<P: ParameterSet> {
+    pkpv: PolyVec<P>,
+    at: Array<PolyVec<P>, P::Rank>,
+}
+
+impl<P: ParameterSet> IndcpaExpandedPublicKey<P> {
+    pub(crate) fn zero() -> Self {
+        IndcpaExpandedPublicKey {
+            pkpv: PolyVec::new(),
+            at: Array::from_fn(|_| PolyVec::new()),
+        }
+    }
+
+    /// Unpack a serialized public key into this key, in place
+    pub(crate) fn expand<S: Symmetric>(&mut self, pk: &Array<u8, P::IndcpaPkBytes>) {
+        let (polypart, seed) = pk.split_at(P::POLYVEC_BYTES);
+        self.pkpv = PolyVec::from_bytes(polypart.try_into().unwrap());
+        let seed = seed.try_into().unwrap();
+        for (i, row) in self.at.iter_mut().enumerate() {
+            for (j, poly) in row.vec.iter_mut().enumerate() {
+                *poly = gen_matrix_entry::<P, S>(seed, i, j, true);
             }
         }
-        *polyvec = MaybeUninit::new(new_polyvec);
     }
 
-    unsafe { MaybeUninit::array_assume_init(polys) }
+    /// Multiply the matrix A^T by a vector in the NTT domain
+    fn mul_at(&self, vec: &PolyVec<P>) -> PolyVec<P> {
+        let mut out = PolyVec::<P>::new();
+        for (poly, row) in out.vec.iter_mut().zip(self.at.iter()) {
+            *poly = row.basemul_acc_montgomery(vec);
+        }
+        out
+    }
+}
+
+/// Public key of the CPA-secure scheme, either serialized or expanded
+#[derive(Clone, Copy)]
+pub(crate) enum IndcpaPublicKey<'a, P: ParameterSet> {
+    Packed(&'a Array<u8, P::IndcpaPkBytes>),
+    Expanded(&'a IndcpaExpandedPublicKey<P>),
+}
+
+/// Secret key of the CPA-secure scheme with the vector s unpacked
+pub(crate) struct IndcpaExpandedSecretKey<P: ParameterSet> {
+    skpv: PolyVec<P>,
+}
+
+// The polynomials wipe themselves when dropped
+#[cfg(feature = "zeroize")]
+impl<P: ParameterSet> zeroize::Zeroize for IndcpaExpandedSecretKey<P> {
+    fn zeroize(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.skpv);
+    }
+}
+
+impl<P: ParameterSet> IndcpaExpandedSecretKey<P> {
+    pub(crate) fn zero() -> Self {
+        IndcpaExpandedSecretKey {
+            skpv: PolyVec::new(),
+        }
+    }
+
+    /// Unpack a serialized secret key into this key, in place
+    pub(crate) fn expand(&mut self, sk: &Array<u8, P::PolyVecBytes>) {
+        self.skpv = unpack_sk::<P>(sk);
+    }
+}
+
+/// Secret key of the CPA-secure scheme, either serialized or expanded
+#[derive(Clone, Copy)]
+pub(crate) enum IndcpaSecretKey<'a, P: ParameterSet> {
+    Packed(&'a Array<u8, P::PolyVecBytes>),
+    Expanded(&'a IndcpaExpandedSecretKey<P>),
+}
+
+/// Serialize the secret key
+fn pack_sk<P: ParameterSet>(sk: &PolyVec<P>, output: &mut Array<u8, P::PolyVecBytes>) {
+    sk.to_bytes(output);
+}
+
+/// De-serialize the secret key
+fn unpack_sk<P: ParameterSet>(packed_sk: &Array<u8, P::PolyVecBytes>) -> PolyVec<P> {
+    PolyVec::<P>::from_bytes(packed_sk)
 }
 
-fn gen_a<const K: usize>(seed: &[u8; KYBER_SYMBYTES]) -> [PolyVec<K>; K] {
-    gen_matrix(seed, false)
+/// Serialize the ciphertext as the concatenation of the
+/// compressed polynomial vector b and the compressed polynomial v.
+fn pack_ciphertext<P: ParameterSet>(
+    b: &PolyVec<P>,
+    v: &Poly<P>,
+    output: &mut Array<u8, P::IndcpaBytes>,
+) {
+    let (bpart, vpart) = output.split_at_mut(P::POLYVEC_COMPRESSED_BYTES);
+    b.compress_into(bpart.try_into().unwrap());
+    v.compress_into(vpart.try_into().unwrap());
 }
 
-fn gen_at<const K: usize>(seed: &[u8; KYBER_SYMBYTES]) -> [PolyVec<K>; K] {
-    gen_matrix(seed, true)
+/// De-serialize and decompress the ciphertext
+fn unpack_ciphertext<P: ParameterSet>(
+    packed_ct: &Array<u8, P::IndcpaBytes>,
+) -> (PolyVec<P>, Poly<P>) {
+    let (bpart, vpart) = packed_ct.split_at(P::POLYVEC_COMPRESSED_BYTES);
+    let b = PolyVec::<P>::decompress(bpart.try_into().unwrap());
+    let v = Poly::<P>::decompress(vpart.try_into().unwrap());
+    (b, v)
 }
 
-pub(crate) struct IndcpaPublicKey<const K: usize>
-where
-    [(); kyber_indcpa_pkbytes::<K>()]: ,
-{
-    pk: [u8; kyber_indcpa_pkbytes::<K>()],
+/// The `k`-th encoded polynomial of a packed polynomial vector or public key
+fn poly_bytes(bytes: &[u8], k: usize) -> &[u8; KYBER_POLYBYTES] {
+    bytes[k * KYBER_POLYBYTES..][..KYBER_POLYBYTES]
+        .try_into()
+        .unwrap()
 }
-pub(crate) struct IndcpaSecretKey<const K: usize>
-where
-    [(); kyber_indcpa_skbytes::<K>()]: ,
-{
-    sk: [u8; kyber_indcpa_skbytes::<K>()],
+
+/// The `k`-th encoded polynomial to write, see [`poly_bytes`]
+fn poly_bytes_mut(bytes: &mut [u8], k: usize) -> &mut [u8; KYBER_POLYBYTES] {
+    (&mut bytes[k * KYBER_POLYBYTES..][..KYBER_POLYBYTES])
+        .try_into()
+        .unwrap()
 }
 
-pub(crate) fn indcpa_keypair<const K: usize>(
+/// Generate a public and private key for the CPA-secure
+/// public-key encryption scheme underlying Kyber
+///
+/// Parameters:
+///    seed: randomness that fully determines the key pair
+///    domain_separation: append the rank K to the seed before hashing, as in FIPS 203
+///    pk, sk: the key pair is written here
+pub(crate) fn indcpa_keypair<P: ParameterSet, S: Symmetric>(
     seed: &[u8; KYBER_SYMBYTES],
-) -> (IndcpaPublicKey<K>, IndcpaSecretKey<K>)
-where
-    [(); kyber_indcpa_skbytes::<K>()]: ,
-    [(); kyber_indcpa_pkbytes::<K>()]: ,
-    [(); kyber_eta1::<K>() * KYBER_N / 4]: ,
-{
-    let buf = hash_g(&seed[..]);
+    domain_separation: bool,
+    pk: &mut Array<u8, P::IndcpaPkBytes>,
+    sk: &mut Array<u8, P::PolyVecBytes>,
+) {
+    let mut input = [0u8; KYBER_SYMBYTES + 1];
+    input[..KYBER_SYMBYTES].copy_from_slice(seed);
+    input[KYBER_SYMBYTES] = P::K as u8;
+    let mut buf = if domain_separation {
+        S::hash_g(&input[..])
+    } else {
+        S::hash_g(&input[..KYBER_SYMBYTES])
+    };
+    wipe(&mut input);
     let (publicseed, noiseseed): (&[u8; KYBER_SYMBYTES], &[u8]) = split_array(&buf);
-    let noiseseed= noiseseed.try_into().unwrap();
-
-    let matrix_a = gen_a::<K>(publicseed);
+    let noiseseed = noiseseed.try_into().unwrap();
 
-    let mut spkv = PolyVec::<K>::new();
+    let mut spkv = PolyVec::<P>::new();
     let mut nonce = 0;
     for poly in &mut spkv.vec {
-        *poly = Poly::<K>::from_noise_eta1(noiseseed, nonce);
+        *poly = Poly::<P>::from_noise_eta1::<S>(noiseseed, nonce);
         nonce += 1;
     }
-    let mut e = PolyVec::<K>::new();
-    for poly in &mut e.vec {
-        *poly = Poly::<K>::from_noise_eta1(noiseseed, nonce);
+
+    spkv.ntt();
+
+    // matrix-vector multiplication
+    let mut pkpv = matrix_vec_mul::<P, S>(publicseed, false, &spkv);
+
+    // the error vector is added one polynomial at a time
+    for poly in &mut pkpv.vec {
+        poly.tomont();
+        let mut e = Poly::<P>::from_noise_eta1::<S>(noiseseed, nonce);
         nonce += 1;
+        e.ntt();
+        *poly += &e;
     }
+    pkpv.reduce();
 
-    spkv.ntt();
-    e.ntt();
+    pack_sk(&spkv, sk);
+    pack_pk(&pkpv, publicseed, pk);
+    wipe(&mut buf);
+}
 
-    let pk = IndcpaPublicKey {
-        pk: [0; kyber_indcpa_pkbytes::<K>()],
-    };
-    let sk = IndcpaSecretKey {
-        sk: [0; kyber_indcpa_skbytes::<K>()],
+/// Generate four key pairs at once, see [`indcpa_keypair`]
+///
+/// The keys are generated in lockstep, so that the hashes, the noise
+/// polynomials and the matrix entries of all four keys are each computed by
+/// one call of the four-way symmetric primitives.
+#[allow(clippy::type_complexity)]
+pub(crate) fn indcpa_keypair_x4<P: ParameterSet, S: Symmetric>(
+    seeds: [&[u8; KYBER_SYMBYTES]; 4],
+    domain_separation: bool,
+    mut keys: [(
+        &mut Array<u8, P::IndcpaPkBytes>,
+        &mut Array<u8, P::PolyVecBytes>,
+    ); 4],
+) {
+    let mut inputs = seeds.map(|seed| {
+        let mut input = [0u8; KYBER_SYMBYTES + 1];
+        input[..KYBER_SYMBYTES].copy_from_slice(seed);
+        input[KYBER_SYMBYTES] = P::K as u8;
+        input
+    });
+    let len = if domain_separation {
+        KYBER_SYMBYTES + 1
+    } else {
+        KYBER_SYMBYTES
     };
-    (pk, sk)
+    let mut bufs = S::hash_g_x4(inputs.each_ref().map(|input| &input[..len]));
+    inputs.iter_mut().for_each(|input| wipe(input));
+    let publicseeds = bufs.each_ref().map(|buf| split_array(buf).0);
+    let noiseseeds = bufs
+        .each_ref()
+        .map(|buf| buf[KYBER_SYMBYTES..].try_into().unwrap());
+
+    // the vectors s are packed into the secret keys straight away and
+    // unpacked one polynomial at a time, so that they are not held twice
+    for k in 0..P::K {
+        let polys = Poly::<P>::from_noise_eta1_x4::<S>(noiseseeds, k as u8);
+        for ((_, sk), mut poly) in keys.iter_mut().zip(polys) {
+            poly.ntt();
+            poly.to_bytes(poly_bytes_mut(sk, k));
+        }
+    }
+
+    // matrix-vector multiplication, one entry of the four matrices at a
+    // time, with each row of the four results written straight into the
+    // public keys
+    for i in 0..P::K {
+        let mut rows: [Poly<P>; 4] = core::array::from_fn(|_| Poly::zero());
+        for j in 0..P::K {
+            let entries = gen_matrix_entry_x4::<P, S>(publicseeds, i, j);
+            for ((row, (_, sk)), entry) in rows.iter_mut().zip(&keys).zip(entries) {
+                let s = Poly::<P>::from_bytes(poly_bytes(sk, j));
+                *row += entry.basemul_montgomery(&s);
+            }
+        }
+        let errors = Poly::<P>::from_noise_eta1_x4::<S>(noiseseeds, (P::K + i) as u8);
+        for ((row, (pk, _)), mut e) in rows.iter_mut().zip(&mut keys).zip(errors) {
+            row.reduce();
+            row.tomont();
+            e.ntt();
+            *row += &e;
+            row.reduce();
+            row.to_bytes(poly_bytes_mut(pk, i));
+        }
+    }
+
+    for ((pk, _), publicseed) in keys.iter_mut().zip(publicseeds) {
+        pk[P::POLYVEC_BYTES..].copy_from_slice(publicseed);
+    }
+    bufs.iter_mut().for_each(|buf| wipe(buf));
+}
+
+/// Encrypt a message under the CPA-secure public-key encryption scheme
+///
+/// Parameters:
+///    pk: public key
+///    msg: message to encrypt
+///    coins: random coins that fully determine the ciphertext
+///    ct: the ciphertext is written here
+pub(crate) fn indcpa_enc<P: ParameterSet, S: Symmetric>(
+    pk: IndcpaPublicKey<'_, P>,
+    msg: &[u8; KYBER_INDCPA_MSGBYTES],
+    coins: &[u8; KYBER_SYMBYTES],
+    ct: &mut Array<u8, P::IndcpaBytes>,
+) {
+    match pk {
+        IndcpaPublicKey::Packed(pk) => {
+            let (pkpv, seed) = unpack_pk::<P>(pk);
+            let mul_at = |sp: &PolyVec<P>| matrix_vec_mul::<P, S>(&seed, true, sp);
+            enc::<P, S>(&pkpv, mul_at, msg, coins, ct);
+        }
+        IndcpaPublicKey::Expanded(pk) => {
+            enc::<P, S>(&pk.pkpv, |sp| pk.mul_at(sp), msg, coins, ct);
+        }
+    }
+}
+
+/// Encrypt with the vector t of a public key and a function that
+/// multiplies by its matrix A^T
+fn enc<P: ParameterSet, S: Symmetric>(
+    pkpv: &PolyVec<P>,
+    mul_at: impl Fn(&PolyVec<P>) -> PolyVec<P>,
+    msg: &[u8; KYBER_INDCPA_MSGBYTES],
+    coins: &[u8; KYBER_SYMBYTES],
+    ct: &mut Array<u8, P::IndcpaBytes>,
+) {
+    let mut sp = PolyVec::<P>::new();
+    let mut nonce = 0;
+    for poly in &mut sp.vec {
+        *poly = Poly::<P>::from_noise_eta1::<S>(coins, nonce);
+        nonce += 1;
+    }
+
+    sp.ntt();
+
+    // matrix-vector multiplication
+    let mut b = mul_at(&sp);
+    let mut v = pkpv.basemul_acc_montgomery(&sp);
+
+    b.invntt();
+    v.invntt();
+
+    // the error vector is added one polynomial at a time
+    for poly in &mut b.vec {
+        *poly += Poly::<P>::from_noise_eta2::<S>(coins, nonce);
+        nonce += 1;
+    }
+    v += Poly::<P>::from_noise_eta2::<S>(coins, nonce);
+    v += Poly::<P>::from_msg(msg);
+    b.reduce();
+    v.reduce();
+
+    pack_ciphertext(&b, &v, ct);
+}
+
+/// Decrypt a ciphertext under the CPA-secure public-key encryption scheme
+///
+/// Parameters:
+///    sk: secret key
+///    ct: ciphertext to decrypt
+///    msg: the message is written here
+pub(crate) fn indcpa_dec<P: ParameterSet>(
+    sk: IndcpaSecretKey<'_, P>,
+    ct: &Array<u8, P::IndcpaBytes>,
+    msg: &mut [u8; KYBER_INDCPA_MSGBYTES],
+) {
+    match sk {
+        IndcpaSecretKey::Packed(sk) => dec(&unpack_sk::<P>(sk), ct, msg),
+        IndcpaSecretKey::Expanded(sk) => dec(&sk.skpv, ct, msg),
+    }
+}
+
+/// Decrypt with the unpacked vector s of a secret key
+fn dec<P: ParameterSet>(
+    skpv: &PolyVec<P>,
+    ct: &Array<u8, P::IndcpaBytes>,
+    msg: &mut [u8; KYBER_INDCPA_MSGBYTES],
+) {
+    let (mut b, v) = unpack_ciphertext::<P>(ct);
+
+    b.ntt();
+    let mut mp = skpv.basemul_acc_montgomery(&b);
+    mp.invntt();
+
+    let mut mp = v - mp;
+    mp.reduce();
+
+    mp.to_msg(msg);
 }
 
 #[cfg(test)]
@@ -176,16 +529,86 @@ mod test {
 
     #[test]
     fn test_pack_unpack() {
-        const K: usize = 3;
-        let pk = PolyVec::<K>::random();
+        type P = Params768;
+        let pk = PolyVec::<P>::random();
         let seed: [u8; KYBER_SYMBYTES] = random_array();
 
-        let mut output = [0; kyber_indcpa_pkbytes::<K>()];
+        let mut output = Array::default();
         pack_pk(&pk, &seed, &mut output);
 
         let (pk2, seed2) = unpack_pk(&output);
 
         assert_eq!(pk, pk2);
         assert_eq!(seed, seed2);
+        assert!(check_pk::<P>(&output));
+
+        // set the first coefficient to 4095, which is not reduced mod q
+        output[0] = 0xff;
+        output[1] |= 0x0f;
+        assert!(!check_pk::<P>(&output));
+    }
+
+    fn check_enc_dec<P: ParameterSet, S: Symmetric>() {
+        let mut pk = Array::default();
+        let mut sk = Array::default();
+        indcpa_keypair::<P, S>(&random_array(), false, &mut pk, &mut sk);
+        let msg = random_array();
+        let mut ct = Array::default();
+        let coins = random_array();
+        indcpa_enc::<P, S>(IndcpaPublicKey::Packed(&pk), &msg, &coins, &mut ct);
+        let mut decrypted = [0u8; KYBER_INDCPA_MSGBYTES];
+        indcpa_dec::<P>(IndcpaSecretKey::Packed(&sk), &ct, &mut decrypted);
+        assert_eq!(msg, decrypted);
+
+        // the expanded keys give the same results
+        let mut expanded = IndcpaExpandedPublicKey::<P>::zero();
+        expanded.expand::<S>(&pk);
+        let mut ct2 = Array::default();
+        indcpa_enc::<P, S>(IndcpaPublicKey::Expanded(&expanded), &msg, &coins, &mut ct2);
+        assert_eq!(ct, ct2);
+
+        let mut expanded = IndcpaExpandedSecretKey::<P>::zero();
+        expanded.expand(&sk);
+        let mut decrypted = [0u8; KYBER_INDCPA_MSGBYTES];
+        indcpa_dec::<P>(IndcpaSecretKey::Expanded(&expanded), &ct, &mut decrypted);
+        assert_eq!(msg, decrypted);
+    }
+
+    fn check_keypair_x4<P: ParameterSet, S: Symmetric>(domain_separation: bool) {
+        let seeds: [[u8; KYBER_SYMBYTES]; 4] = core::array::from_fn(|_| random_array());
+        let mut pks: [Array<u8, P::IndcpaPkBytes>; 4] = Default::default();
+        let mut sks: [Array<u8, P::PolyVecBytes>; 4] = Default::default();
+        let [pk0, pk1, pk2, pk3] = &mut pks;
+        let [sk0, sk1, sk2, sk3] = &mut sks;
+        let keys = [(pk0, sk0), (pk1, sk1), (pk2, sk2), (pk3, sk3)];
+        indcpa_keypair_x4::<P, S>(seeds.each_ref(), domain_separation, keys);
+
+        for ((seed, pk), sk) in seeds.iter().zip(&pks).zip(&sks) {
+            let mut pk2 = Array::default();
+            let mut sk2 = Array::default();
+            indcpa_keypair::<P, S>(seed, domain_separation, &mut pk2, &mut sk2);
+            assert_eq!(*pk, pk2);
+            assert_eq!(*sk, sk2);
+        }
+    }
+
+    #[test]
+    fn test_keypair_x4() {
+        for domain_separation in [false, true] {
+            check_keypair_x4::<Params512, Fips202>(domain_separation);
+            check_keypair_x4::<Params768, Fips202>(domain_separation);
+            check_keypair_x4::<Params1024, Fips202>(domain_separation);
+        }
+        check_keypair_x4::<Params768, Nineties>(false);
+    }
+
+    #[test]
+    fn test_enc_dec() {
+        check_enc_dec::<Params512, Fips202>();
+        check_enc_dec::<Params768, Fips202>();
+        check_enc_dec::<Params1024, Fips202>();
+        check_enc_dec::<Params512, Nineties>();
+        check_enc_dec::<Params768, Nineties>();
+        check_enc_dec::<Params1024, Nineties>();
     }
 }
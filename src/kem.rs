@@ -1,49 +1,2059 @@
-use crate::params::*;
+//! The Kyber and ML-KEM key encapsulation mechanisms
+//!
+//! Every scheme has its own key and ciphertext types, so mixing up schemes
+//! with the same sizes does not compile:
+//!
+//! ```compile_fail
+//! use kyber::kem::{kyber768_90s, ml_kem_768};
+//!
+//! let (pk, _) = kyber768_90s::keypair(&mut rand::thread_rng()).unwrap();
+//! let (_, sk) = ml_kem_768::keypair(&mut rand::thread_rng()).unwrap();
+//! let (ct, _) = kyber768_90s::encaps(&pk, &mut rand::thread_rng()).unwrap();
+//! ml_kem_768::decaps(&ct, &sk);
+//! ```
+//!
+//! # Stack usage
+//!
+//! Keys and ciphertexts are stored inline, and all temporaries live on the
+//! stack. The matrix A is sampled one polynomial at a time rather than
+//! held in memory. Approximate peak stack use of a release build on x86-64
+//! Linux, including the returned keys and ciphertexts, which varies with
+//! the compiler version and the target:
+//!
+//! | Parameter set | `keypair` | `encaps` | `decaps` |
+//! |---------------|-----------|----------|----------|
+//! | 512           | 17 KiB    | 12 KiB   | 12 KiB   |
+//! | 768           | 22 KiB    | 15 KiB   | 14 KiB   |
+//! | 1024          | 28 KiB    | 19 KiB   | 17 KiB   |
+//!
+//! `keypair_batch` works on four keys at a time and uses about 42 KiB. The
+//! `zeroize` feature adds a few KiB to key generation, and the `pct`
+//! feature adds an `encaps` and a `decaps`. Debug builds use up to four
+//! times as much.
+//!
+//! With any features, every operation fits in 64 KiB of stack in a release
+//! build and in 128 KiB in a debug build, and `keypair_batch` fits in twice
+//! that. The tests run the operations on threads with stacks of these
+//! sizes. With the `alloc` feature, the `*_boxed` functions put keys and
+//! ciphertexts on the heap instead, and copy, expand or generate them
+//! straight into the allocation rather than through the stack.
 
+use core::fmt;
+
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, vec::Vec};
+
+use hybrid_array::Array;
+use rand_core::{CryptoRng, RngCore};
+use subtle::{Choice, ConstantTimeEq};
+
+use crate::{
+    error::Error,
+    indcpa::{
+        check_pk, indcpa_dec, indcpa_enc, indcpa_keypair, indcpa_keypair_x4,
+        IndcpaExpandedPublicKey, IndcpaExpandedSecretKey, IndcpaPublicKey, IndcpaSecretKey,
+    },
+    params::*,
+    symmetric::{hash_j, Fips202, Nineties, Symmetric},
+    utils::{split_array, wipe},
+    verify::{cmov, ct_eq},
+};
 
 /// Kyber Public Key
-struct KyberPublicKey<const SIZE: usize>([u8; SIZE]);
+struct KyberPublicKey<P: ParameterSet>(Array<u8, P::IndcpaPkBytes>);
 
-struct KyberSecretKey<const SIZE: usize>([u8; SIZE]);
+struct KyberSecretKey<P: ParameterSet>(Array<u8, P::SkBytes>);
 
-impl<const K: usize> AsRef<[u8]> for KyberPublicKey<K> {
+impl<P: ParameterSet> AsRef<[u8]> for KyberPublicKey<P> {
     fn as_ref(&self) -> &[u8] {
         &self.0
     }
 }
 
-impl<const K: usize> AsRef<[u8]> for KyberSecretKey<K> {
+impl<P: ParameterSet> AsRef<[u8]> for KyberSecretKey<P> {
     fn as_ref(&self) -> &[u8] {
         &self.0
     }
 }
 
-pub struct Ciphertext<const SIZE: usize>([u8; SIZE]);
-
+/// Shared secret established by the KEM
+///
+/// Shared secrets can only be compared with [`ConstantTimeEq`], and their
+/// `Debug` output does not show the bytes.
 pub struct SharedSecret([u8; KYBER_SSBYTES]);
 
+impl ConstantTimeEq for SharedSecret {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        ct_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for SharedSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedSecret(..)")
+    }
+}
+
+impl SharedSecret {
+    /// Length of the shared secret in bytes
+    pub const LEN: usize = KYBER_SSBYTES;
+
+    /// Create a shared secret from its bytes
+    pub fn from_bytes(bytes: [u8; KYBER_SSBYTES]) -> Self {
+        SharedSecret(bytes)
+    }
+
+    /// The shared secret
+    pub fn as_bytes(&self) -> &[u8; KYBER_SSBYTES] {
+        &self.0
+    }
+
+    /// Consume the shared secret and return its bytes
+    pub fn into_bytes(self) -> [u8; KYBER_SSBYTES] {
+        self.0
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for SharedSecret {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for SharedSecret {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(self);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for SharedSecret {}
+
+impl TryFrom<&[u8]> for SharedSecret {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Error> {
+        let bytes = bytes
+            .try_into()
+            .map_err(|_| Error::InvalidSharedSecretLength)?;
+        Ok(SharedSecret(bytes))
+    }
+}
+
+/// Decapsulation key in seed form: the 64-byte `d || z` from which the
+/// full secret key is deterministically expanded
+///
+/// FIPS 203 allows storing this instead of the expanded secret key.
+/// The same seed expands to different keys for each parameter set.
+/// Seeds can only be compared with [`ConstantTimeEq`].
+#[derive(Clone)]
+pub struct DecapsulationKeySeed([u8; 2 * KYBER_SYMBYTES]);
+
+impl ConstantTimeEq for DecapsulationKeySeed {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        ct_eq(&self.0, &other.0)
+    }
+}
+
+impl DecapsulationKeySeed {
+    /// Length of the encoded seed in bytes
+    pub const LEN: usize = 2 * KYBER_SYMBYTES;
+
+    /// Create a seed from the key generation randomness `d` and the
+    /// implicit rejection value `z`
+    pub fn new(d: &[u8; KYBER_SYMBYTES], z: &[u8; KYBER_SYMBYTES]) -> Self {
+        let mut seed = [0u8; 2 * KYBER_SYMBYTES];
+        seed[..KYBER_SYMBYTES].copy_from_slice(d);
+        seed[KYBER_SYMBYTES..].copy_from_slice(z);
+        DecapsulationKeySeed(seed)
+    }
+
+    /// Generate a new random seed from a cryptographically secure RNG
+    pub fn generate(rng: &mut (impl CryptoRng + RngCore)) -> Result<Self, Error> {
+        let mut seed = [0u8; 2 * KYBER_SYMBYTES];
+        rng.try_fill_bytes(&mut seed)
+            .map_err(|_| Error::RngFailure)?;
+        Ok(DecapsulationKeySeed(seed))
+    }
+
+    /// Create a seed from its 64-byte encoding `d || z`
+    pub fn from_bytes(bytes: [u8; 2 * KYBER_SYMBYTES]) -> Self {
+        DecapsulationKeySeed(bytes)
+    }
+
+    /// The 64-byte encoding `d || z`
+    pub fn as_bytes(&self) -> &[u8; 2 * KYBER_SYMBYTES] {
+        &self.0
+    }
+
+    /// Consume the seed and return its 64-byte encoding `d || z`
+    pub fn into_bytes(self) -> [u8; 2 * KYBER_SYMBYTES] {
+        self.0
+    }
+
+    fn d(&self) -> &[u8; KYBER_SYMBYTES] {
+        split_array(&self.0).0
+    }
+
+    fn z(&self) -> &[u8; KYBER_SYMBYTES] {
+        self.0[KYBER_SYMBYTES..].try_into().unwrap()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for DecapsulationKeySeed {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for DecapsulationKeySeed {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(self);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for DecapsulationKeySeed {}
+
+impl TryFrom<&[u8]> for DecapsulationKeySeed {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Error> {
+        let bytes = bytes.try_into().map_err(|_| Error::InvalidKeyLength)?;
+        Ok(DecapsulationKeySeed(bytes))
+    }
+}
+
+/// Generate a key pair for the CCA-secure KEM
+///
+/// Both Kyber and ML-KEM store `indcpa_sk || pk || H(pk) || z` as secret key.
+///
+/// Parameters:
+///    coins: randomness for the CPA-secure key generation
+///    z: secret value used by decapsulation on implicit rejection
+///    domain_separation: use the FIPS 203 (ML-KEM) key generation
+///    sk: the secret key is written here; it embeds the public key
+fn kem_keypair<P: ParameterSet, S: Symmetric>(
+    coins: &[u8; KYBER_SYMBYTES],
+    z: &[u8; KYBER_SYMBYTES],
+    domain_separation: bool,
+    sk: &mut KyberSecretKey<P>,
+) {
+    // sk = indcpa_sk || pk || H(pk) || z
+    // written in place, so that no copy of the secret key is left behind
+    let (skpart, rest) = sk.0.split_at_mut(P::INDCPA_SK_BYTES);
+    let (pkpart, rest) = rest.split_at_mut(P::INDCPA_PK_BYTES);
+    indcpa_keypair::<P, S>(
+        coins,
+        domain_separation,
+        pkpart.try_into().unwrap(),
+        skpart.try_into().unwrap(),
+    );
+    let (hpart, zpart) = rest.split_at_mut(KYBER_SYMBYTES);
+    hpart.copy_from_slice(&S::hash_h(pkpart));
+    zpart.copy_from_slice(z);
+}
+
+/// Generate four key pairs at once, see [`kem_keypair`]
+///
+/// Each seed is `coins || z`.
+fn kem_keypair_x4<P: ParameterSet, S: Symmetric>(
+    seeds: [&[u8; 2 * KYBER_SYMBYTES]; 4],
+    domain_separation: bool,
+    sks: [&mut KyberSecretKey<P>; 4],
+) {
+    let mut parts = sks.map(|sk| {
+        let (skpart, rest) = sk.0.split_at_mut(P::INDCPA_SK_BYTES);
+        let (pkpart, rest) = rest.split_at_mut(P::INDCPA_PK_BYTES);
+        let (hpart, zpart) = rest.split_at_mut(KYBER_SYMBYTES);
+        (skpart, pkpart, hpart, zpart)
+    });
+    indcpa_keypair_x4::<P, S>(
+        seeds.map(|seed| split_array(seed).0),
+        domain_separation,
+        parts.each_mut().map(|(skpart, pkpart, _, _)| {
+            (
+                (&mut **pkpart).try_into().unwrap(),
+                (&mut **skpart).try_into().unwrap(),
+            )
+        }),
+    );
+    let hashes = S::hash_h_x4(parts.each_ref().map(|(_, pkpart, _, _)| &pkpart[..]));
+    for ((_, _, hpart, zpart), (hash, seed)) in parts.into_iter().zip(hashes.iter().zip(seeds)) {
+        hpart.copy_from_slice(hash);
+        zpart.copy_from_slice(&seed[KYBER_SYMBYTES..]);
+    }
+}
+
+/// Split a secret key into the CPA-secure secret key, the public key,
+/// the hash of the public key and the implicit rejection value `z`
+#[allow(clippy::type_complexity)]
+fn unpack_sk<P: ParameterSet>(
+    sk: &KyberSecretKey<P>,
+) -> (
+    &Array<u8, P::PolyVecBytes>,
+    &Array<u8, P::IndcpaPkBytes>,
+    &[u8; KYBER_SYMBYTES],
+    &[u8; KYBER_SYMBYTES],
+) {
+    let (skpart, rest) = sk.0.split_at(P::INDCPA_SK_BYTES);
+    let (pkpart, rest) = rest.split_at(P::INDCPA_PK_BYTES);
+    let (hpk, z) = rest.split_at(KYBER_SYMBYTES);
+    (
+        skpart.try_into().unwrap(),
+        pkpart.try_into().unwrap(),
+        hpk.try_into().unwrap(),
+        z.try_into().unwrap(),
+    )
+}
+
+/// Generate a ciphertext and shared secret for a public key
+/// following the round-3 Kyber specification
+///
+/// Parameters:
+///    pk: public key to encapsulate to
+///    hpk: hash of the encoded public key
+///    m: randomness that fully determines the ciphertext
+///    ct: the ciphertext is written here
+fn kyber_enc<P: ParameterSet, S: Symmetric>(
+    pk: IndcpaPublicKey<'_, P>,
+    hpk: &[u8; KYBER_SYMBYTES],
+    m: &[u8; KYBER_SYMBYTES],
+    ct: &mut Array<u8, P::IndcpaBytes>,
+) -> SharedSecret {
+    let mut buf = [0u8; 2 * KYBER_SYMBYTES];
+    // Don't release system RNG output
+    buf[..KYBER_SYMBYTES].copy_from_slice(&S::hash_h(m));
+    // Multitarget countermeasure for coins + contributory KEM
+    buf[KYBER_SYMBYTES..].copy_from_slice(hpk);
+    let mut kr = S::hash_g(&buf);
+
+    let (msg, _) = split_array(&buf);
+    let (_, coins) = kr.split_at(KYBER_SYMBYTES);
+    indcpa_enc::<P, S>(pk, msg, coins.try_into().unwrap(), ct);
+
+    // overwrite coins in kr with H(c)
+    kr[KYBER_SYMBYTES..].copy_from_slice(&S::hash_h(ct));
+
+    let ss = SharedSecret(S::kdf(&kr));
+    wipe(&mut buf);
+    wipe(&mut kr);
+    ss
+}
+
+/// Recover the shared secret from a ciphertext
+/// following the round-3 Kyber specification
+///
+/// If the ciphertext does not re-encrypt to itself, this returns a
+/// pseudo-random value derived from the secret `z` (implicit rejection).
+///
+/// Parameters:
+///    ct: ciphertext to decapsulate
+///    sk: CPA-secure secret key
+///    pk: public key, to re-encrypt with
+///    hpk: hash of the encoded public key
+///    z: implicit rejection value
+fn kyber_dec<P: ParameterSet, S: Symmetric>(
+    ct: &Array<u8, P::IndcpaBytes>,
+    sk: IndcpaSecretKey<'_, P>,
+    pk: IndcpaPublicKey<'_, P>,
+    hpk: &[u8; KYBER_SYMBYTES],
+    z: &[u8; KYBER_SYMBYTES],
+) -> SharedSecret {
+    let mut buf = [0u8; 2 * KYBER_SYMBYTES];
+    let (msg, _) = buf.split_at_mut(KYBER_SYMBYTES);
+    indcpa_dec::<P>(sk, ct, msg.try_into().unwrap());
+    // Multitarget countermeasure for coins + contributory KEM
+    buf[KYBER_SYMBYTES..].copy_from_slice(hpk);
+    let mut kr = S::hash_g(&buf);
+
+    // re-encrypt with the coins derived from the decrypted message
+    let (msg, _) = split_array(&buf);
+    let (_, coins) = kr.split_at(KYBER_SYMBYTES);
+    let mut cmp = Array::default();
+    indcpa_enc::<P, S>(pk, msg, coins.try_into().unwrap(), &mut cmp);
+
+    // overwrite coins in kr with H(c)
+    kr[KYBER_SYMBYTES..].copy_from_slice(&S::hash_h(ct));
+
+    // implicit rejection: replace the pre-key by z if the ciphertexts differ
+    let fail = !ct_eq(&cmp, ct);
+    cmov(&mut kr[..KYBER_SYMBYTES], z, fail);
+
+    let ss = SharedSecret(S::kdf(&kr));
+    wipe(&mut buf);
+    wipe(&mut kr);
+    wipe(&mut cmp);
+    ss
+}
+
+/// Generate a ciphertext and shared secret for a public key
+/// following FIPS 203 (ML-KEM)
+///
+/// Parameters:
+///    pk: public key to encapsulate to
+///    hpk: hash of the encoded public key
+///    m: randomness that fully determines the ciphertext
+///    ct: the ciphertext is written here
+fn mlkem_enc<P: ParameterSet, S: Symmetric>(
+    pk: IndcpaPublicKey<'_, P>,
+    hpk: &[u8; KYBER_SYMBYTES],
+    m: &[u8; KYBER_SYMBYTES],
+    ct: &mut Array<u8, P::IndcpaBytes>,
+) -> SharedSecret {
+    // (K, r) = G(m || H(ek))
+    let mut buf = [0u8; 2 * KYBER_SYMBYTES];
+    buf[..KYBER_SYMBYTES].copy_from_slice(m);
+    buf[KYBER_SYMBYTES..].copy_from_slice(hpk);
+    let mut kr = S::hash_g(&buf);
+
+    let (ss, coins) = kr.split_at(KYBER_SYMBYTES);
+    indcpa_enc::<P, S>(pk, m, coins.try_into().unwrap(), ct);
+    let ss = SharedSecret(ss.try_into().unwrap());
+    wipe(&mut buf);
+    wipe(&mut kr);
+    ss
+}
+
+/// Recover the shared secret from a ciphertext
+/// following FIPS 203 (ML-KEM)
+///
+/// If the ciphertext does not re-encrypt to itself, this returns
+/// J(z || c) instead (implicit rejection).
+///
+/// Parameters:
+///    ct: ciphertext to decapsulate
+///    sk: CPA-secure secret key
+///    pk: public key, to re-encrypt with
+///    hpk: hash of the encoded public key
+///    z: implicit rejection value
+fn mlkem_dec<P: ParameterSet, S: Symmetric>(
+    ct: &Array<u8, P::IndcpaBytes>,
+    sk: IndcpaSecretKey<'_, P>,
+    pk: IndcpaPublicKey<'_, P>,
+    hpk: &[u8; KYBER_SYMBYTES],
+    z: &[u8; KYBER_SYMBYTES],
+) -> SharedSecret {
+    // (K', r') = G(m' || h)
+    let mut buf = [0u8; 2 * KYBER_SYMBYTES];
+    let (msg, _) = buf.split_at_mut(KYBER_SYMBYTES);
+    indcpa_dec::<P>(sk, ct, msg.try_into().unwrap());
+    buf[KYBER_SYMBYTES..].copy_from_slice(hpk);
+    let mut kr = S::hash_g(&buf);
+
+    // re-encrypt with the coins derived from the decrypted message
+    let (msg, _) = split_array(&buf);
+    let (ss, coins) = kr.split_at(KYBER_SYMBYTES);
+    let mut cmp = Array::default();
+    indcpa_enc::<P, S>(pk, msg, coins.try_into().unwrap(), &mut cmp);
+
+    // implicit rejection: return J(z || c) if the ciphertexts differ
+    let fail = !ct_eq(&cmp, ct);
+    let mut ss = SharedSecret(ss.try_into().unwrap());
+    let mut rejection = hash_j(z, ct);
+    cmov(&mut ss.0, &rejection, fail);
+    wipe(&mut rejection);
+    wipe(&mut buf);
+    wipe(&mut kr);
+    wipe(&mut cmp);
+    ss
+}
+
+/// Defines the public key, secret key and KEM operations of a Kyber
+/// parameter set in the enclosing module.
+///
+/// Parameters:
+///    $params: the parameter set
+///    $symmetric: the hash functions, XOF and PRF to instantiate it with
+///    $domain_separation: whether key generation follows FIPS 203
+///    $enc, $dec: the encapsulation and decapsulation of the FO transform
+macro_rules! kyber_kem {
+    ($params: ident, $symmetric: ident, $domain_separation: expr, $enc: ident, $dec: ident) => {
+        use super::*;
+
+        const PK_BYTES: usize = $params::PK_BYTES;
+        const SK_BYTES: usize = $params::SK_BYTES;
+        const CT_SIZE: usize = $params::CT_BYTES;
+
+        pub struct PublicKey(pub(super) KyberPublicKey<$params>);
+
+        impl Clone for PublicKey {
+            fn clone(&self) -> Self {
+                PublicKey(KyberPublicKey(self.0 .0.clone()))
+            }
+        }
+
+        impl PartialEq for PublicKey {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 .0 == other.0 .0
+            }
+        }
+
+        impl Eq for PublicKey {}
+
+        impl fmt::Debug for PublicKey {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_tuple("PublicKey").field(&self.0 .0).finish()
+            }
+        }
+
+        impl PublicKey {
+            /// Length of the encoded public key in bytes
+            pub const LEN: usize = PK_BYTES;
+
+            /// Parse a public key, for example one received from a peer
+            ///
+            /// This performs the FIPS 203 modulus check, which rejects keys
+            /// with coefficients that are not reduced modulo q.
+            pub fn from_bytes(bytes: [u8; PK_BYTES]) -> Result<Self, Error> {
+                let bytes = Array::from(bytes);
+                if !check_pk::<$params>(&bytes) {
+                    return Err(Error::EncapsulationKeyModulusCheck);
+                }
+                Ok(PublicKey(KyberPublicKey(bytes)))
+            }
+
+            /// Parse a public key from a slice, see [`PublicKey::from_bytes`]
+            pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+                let bytes: [u8; PK_BYTES] =
+                    bytes.try_into().map_err(|_| Error::InvalidKeyLength)?;
+                Self::from_bytes(bytes)
+            }
+
+            /// Parse a public key from a slice into a heap allocation,
+            /// see [`PublicKey::from_bytes`]
+            #[cfg(feature = "alloc")]
+            pub fn try_from_bytes_boxed(bytes: &[u8]) -> Result<Box<Self>, Error> {
+                if bytes.len() != PK_BYTES {
+                    return Err(Error::InvalidKeyLength);
+                }
+                let mut pk = Box::new(Self::zero());
+                pk.0 .0.copy_from_slice(bytes);
+                if !check_pk::<$params>(&pk.0 .0) {
+                    return Err(Error::EncapsulationKeyModulusCheck);
+                }
+                Ok(pk)
+            }
+
+            fn zero() -> Self {
+                PublicKey(KyberPublicKey(Array::default()))
+            }
+
+            /// The encoded public key
+            pub fn as_bytes(&self) -> &[u8; PK_BYTES] {
+                self.0 .0.as_ref()
+            }
+
+            /// Consume the public key and return its encoding
+            pub fn into_bytes(self) -> [u8; PK_BYTES] {
+                self.0 .0.into()
+            }
+        }
+
+        /// Ciphertext of this scheme
+        ///
+        /// Every scheme has its own ciphertext type, so that a ciphertext
+        /// cannot be passed to another scheme with the same sizes.
+        pub struct Ciphertext(pub(super) [u8; CT_SIZE]);
+
+        impl Ciphertext {
+            /// Length of the encoded ciphertext in bytes
+            pub const LEN: usize = CT_SIZE;
+
+            /// Create a ciphertext from its encoding, for example one received
+            /// from a peer
+            pub fn from_bytes(bytes: [u8; CT_SIZE]) -> Self {
+                Ciphertext(bytes)
+            }
+
+            /// Create a heap-allocated ciphertext from a slice
+            #[cfg(feature = "alloc")]
+            pub fn try_from_bytes_boxed(bytes: &[u8]) -> Result<Box<Self>, Error> {
+                if bytes.len() != CT_SIZE {
+                    return Err(Error::InvalidCiphertextLength);
+                }
+                let mut ct = Box::new(Ciphertext([0; CT_SIZE]));
+                ct.0.copy_from_slice(bytes);
+                Ok(ct)
+            }
+
+            /// The encoded ciphertext
+            pub fn as_bytes(&self) -> &[u8; CT_SIZE] {
+                &self.0
+            }
+
+            /// Consume the ciphertext and return its encoding
+            pub fn into_bytes(self) -> [u8; CT_SIZE] {
+                self.0
+            }
+        }
+
+        impl TryFrom<&[u8]> for Ciphertext {
+            type Error = Error;
+
+            fn try_from(bytes: &[u8]) -> Result<Self, Error> {
+                let bytes = bytes
+                    .try_into()
+                    .map_err(|_| Error::InvalidCiphertextLength)?;
+                Ok(Ciphertext(bytes))
+            }
+        }
+
+        impl TryFrom<&[u8]> for PublicKey {
+            type Error = Error;
+
+            fn try_from(bytes: &[u8]) -> Result<Self, Error> {
+                Self::try_from_bytes(bytes)
+            }
+        }
+
+        /// Public key with the matrix A^T, the vector t and H(pk) precomputed,
+        /// for encapsulating to the same key many times
+        ///
+        /// Sampling A^T dominates the cost of encapsulation, so
+        /// [`encaps_expanded`] is considerably faster than [`encaps`].
+        /// The matrix takes `512 * k^2` bytes, 8 KiB for the 1024 parameter
+        /// set; with the `alloc` feature, `ExpandedPublicKey::new_boxed`
+        /// keeps it off the stack.
+        pub struct ExpandedPublicKey {
+            key: IndcpaExpandedPublicKey<$params>,
+            hpk: [u8; KYBER_SYMBYTES],
+        }
+
+        impl ExpandedPublicKey {
+            /// Expand a public key
+            pub fn new(pk: &PublicKey) -> Self {
+                let mut expanded = Self::zero();
+                expanded.expand(pk);
+                expanded
+            }
+
+            /// Expand a public key into a heap allocation
+            #[cfg(feature = "alloc")]
+            pub fn new_boxed(pk: &PublicKey) -> Box<Self> {
+                let mut expanded = Box::new(Self::zero());
+                expanded.expand(pk);
+                expanded
+            }
+
+            fn zero() -> Self {
+                ExpandedPublicKey {
+                    key: IndcpaExpandedPublicKey::zero(),
+                    hpk: [0; KYBER_SYMBYTES],
+                }
+            }
+
+            fn expand(&mut self, pk: &PublicKey) {
+                self.key.expand::<$symmetric>(&pk.0 .0);
+                self.hpk = $symmetric::hash_h(&pk.0 .0);
+            }
+        }
+
+        impl From<&PublicKey> for ExpandedPublicKey {
+            fn from(pk: &PublicKey) -> Self {
+                Self::new(pk)
+            }
+        }
+
+        /// Expanded secret key
+        ///
+        /// The seed a key was expanded from cannot be recovered from the key
+        /// alone. To export a key in seed form later, keep it as a
+        /// [`SeededSecretKey`], for example from [`keypair_seeded`].
+        pub struct SecretKey(pub(super) KyberSecretKey<$params>);
+
+        #[cfg(feature = "zeroize")]
+        impl zeroize::Zeroize for SecretKey {
+            fn zeroize(&mut self) {
+                self.0 .0.zeroize();
+            }
+        }
+
+        #[cfg(feature = "zeroize")]
+        impl Drop for SecretKey {
+            fn drop(&mut self) {
+                zeroize::Zeroize::zeroize(self);
+            }
+        }
+
+        #[cfg(feature = "zeroize")]
+        impl zeroize::ZeroizeOnDrop for SecretKey {}
+
+        impl ConstantTimeEq for SecretKey {
+            fn ct_eq(&self, other: &Self) -> Choice {
+                ct_eq(&self.0 .0, &other.0 .0)
+            }
+        }
+
+        impl SecretKey {
+            /// Length of the encoded secret key in bytes
+            pub const LEN: usize = SK_BYTES;
+
+            /// Parse an expanded secret key, for example one loaded from storage
+            ///
+            /// This performs the FIPS 203 hash check, see [`SecretKey::validate`].
+            pub fn from_bytes(bytes: [u8; SK_BYTES]) -> Result<Self, Error> {
+                let sk = SecretKey(KyberSecretKey(Array::from(bytes)));
+                sk.validate()?;
+                Ok(sk)
+            }
+
+            /// The encoded secret key `indcpa_sk || pk || H(pk) || z`
+            pub fn as_bytes(&self) -> &[u8; SK_BYTES] {
+                self.0 .0.as_ref()
+            }
+
+            /// Consume the secret key and return its encoding
+            pub fn into_bytes(self) -> [u8; SK_BYTES] {
+                self.0 .0.into()
+            }
+
+            /// Parse an expanded secret key from a slice into a heap
+            /// allocation, see [`SecretKey::from_bytes`]
+            #[cfg(feature = "alloc")]
+            pub fn try_from_bytes_boxed(bytes: &[u8]) -> Result<Box<Self>, Error> {
+                if bytes.len() != SK_BYTES {
+                    return Err(Error::InvalidKeyLength);
+                }
+                let mut sk = Box::new(Self::zero());
+                sk.0 .0.copy_from_slice(bytes);
+                sk.validate()?;
+                Ok(sk)
+            }
+
+            /// Expand a seed into the full secret key
+            pub fn from_seed(seed: &DecapsulationKeySeed) -> Self {
+                let mut sk = Self::zero();
+                sk.expand(seed.d(), seed.z());
+                sk
+            }
+
+            /// Expand a seed into the full secret key in a heap allocation
+            #[cfg(feature = "alloc")]
+            pub fn from_seed_boxed(seed: &DecapsulationKeySeed) -> Box<Self> {
+                let mut sk = Box::new(Self::zero());
+                sk.expand(seed.d(), seed.z());
+                sk
+            }
+
+            fn zero() -> Self {
+                SecretKey(KyberSecretKey(Array::default()))
+            }
+
+            /// Overwrite this key with the key pair derived from `d` and `z`
+            fn expand(&mut self, d: &[u8; KYBER_SYMBYTES], z: &[u8; KYBER_SYMBYTES]) {
+                kem_keypair::<$params, $symmetric>(d, z, $domain_separation, &mut self.0);
+            }
+
+            /// Overwrite four keys with the key pairs derived from the seeds
+            /// `d || z`, see [`SecretKey::expand`]
+            fn expand_x4(sks: [&mut Self; 4], seeds: [&[u8; 2 * KYBER_SYMBYTES]; 4]) {
+                kem_keypair_x4::<$params, $symmetric>(
+                    seeds,
+                    $domain_separation,
+                    sks.map(|sk| &mut sk.0),
+                );
+            }
+
+            /// Check that the hash of the public key embedded in the secret
+            /// key matches the stored hash
+            ///
+            /// This is the decapsulation key check of FIPS 203.
+            pub fn validate(&self) -> Result<(), Error> {
+                let (_, pk, hpk, _) = unpack_sk::<$params>(&self.0);
+                if !bool::from(ct_eq(&$symmetric::hash_h(pk), hpk)) {
+                    return Err(Error::DecapsulationKeyHashMismatch);
+                }
+                Ok(())
+            }
+
+            /// Derive the public key, which is embedded in the secret key
+            pub fn public_key(&self) -> PublicKey {
+                let (_, pk, _, _) = unpack_sk::<$params>(&self.0);
+                PublicKey(KyberPublicKey(pk.clone()))
+            }
+
+            /// Derive the public key into a heap allocation
+            #[cfg(feature = "alloc")]
+            pub fn public_key_boxed(&self) -> Box<PublicKey> {
+                let (_, pk, _, _) = unpack_sk::<$params>(&self.0);
+                let mut boxed = Box::new(PublicKey::zero());
+                boxed.0 .0.copy_from_slice(pk);
+                boxed
+            }
+        }
+
+        impl TryFrom<&[u8]> for SecretKey {
+            type Error = Error;
+
+            fn try_from(bytes: &[u8]) -> Result<Self, Error> {
+                let bytes: [u8; SK_BYTES] =
+                    bytes.try_into().map_err(|_| Error::InvalidKeyLength)?;
+                Self::from_bytes(bytes)
+            }
+        }
+
+        /// Expanded secret key together with the seed it was expanded from
+        ///
+        /// This decapsulates with the expanded key and can still be exported
+        /// in seed form.
+        pub struct SeededSecretKey {
+            sk: SecretKey,
+            seed: DecapsulationKeySeed,
+        }
+
+        #[cfg(feature = "zeroize")]
+        impl zeroize::ZeroizeOnDrop for SeededSecretKey {}
+
+        impl SeededSecretKey {
+            /// Expand a seed into the full secret key and keep the seed
+            pub fn from_seed(seed: DecapsulationKeySeed) -> Self {
+                SeededSecretKey {
+                    sk: SecretKey::from_seed(&seed),
+                    seed,
+                }
+            }
+
+            /// The expanded secret key
+            pub fn secret_key(&self) -> &SecretKey {
+                &self.sk
+            }
+
+            /// The seed the secret key was expanded from
+            pub fn seed(&self) -> &DecapsulationKeySeed {
+                &self.seed
+            }
+
+            /// Split into the expanded secret key and its seed
+            pub fn into_parts(self) -> (SecretKey, DecapsulationKeySeed) {
+                (self.sk, self.seed)
+            }
+        }
+
+        impl From<DecapsulationKeySeed> for SeededSecretKey {
+            fn from(seed: DecapsulationKeySeed) -> Self {
+                Self::from_seed(seed)
+            }
+        }
+
+        /// Secret key with the vector s, the embedded public key and H(pk)
+        /// unpacked, for decapsulating with the same key many times
+        ///
+        /// [`decaps_expanded`] skips unpacking the key and sampling the
+        /// matrix A^T for the re-encryption, which makes it considerably
+        /// faster than [`decaps`]. The key takes `512 * k * (k + 2)` bytes,
+        /// 12 KiB for the 1024 parameter set; with the `alloc` feature,
+        /// `ExpandedSecretKey::new_boxed` keeps it off the stack.
+        pub struct ExpandedSecretKey {
+            sk: IndcpaExpandedSecretKey<$params>,
+            pk: ExpandedPublicKey,
+            z: [u8; KYBER_SYMBYTES],
+        }
+
+        #[cfg(feature = "zeroize")]
+        impl zeroize::Zeroize for ExpandedSecretKey {
+            fn zeroize(&mut self) {
+                self.sk.zeroize();
+                self.z.zeroize();
+            }
+        }
+
+        #[cfg(feature = "zeroize")]
+        impl Drop for ExpandedSecretKey {
+            fn drop(&mut self) {
+                zeroize::Zeroize::zeroize(self);
+            }
+        }
+
+        #[cfg(feature = "zeroize")]
+        impl zeroize::ZeroizeOnDrop for ExpandedSecretKey {}
+
+        impl ExpandedSecretKey {
+            /// Expand a secret key
+            pub fn new(sk: &SecretKey) -> Self {
+                let mut expanded = Self::zero();
+                expanded.expand(sk);
+                expanded
+            }
+
+            /// Expand a secret key into a heap allocation
+            #[cfg(feature = "alloc")]
+            pub fn new_boxed(sk: &SecretKey) -> Box<Self> {
+                let mut expanded = Box::new(Self::zero());
+                expanded.expand(sk);
+                expanded
+            }
+
+            fn zero() -> Self {
+                ExpandedSecretKey {
+                    sk: IndcpaExpandedSecretKey::zero(),
+                    pk: ExpandedPublicKey::zero(),
+                    z: [0; KYBER_SYMBYTES],
+                }
+            }
+
+            fn expand(&mut self, sk: &SecretKey) {
+                let (indcpa_sk, indcpa_pk, hpk, z) = unpack_sk::<$params>(&sk.0);
+                self.sk.expand(indcpa_sk);
+                self.pk.key.expand::<$symmetric>(indcpa_pk);
+                self.pk.hpk = *hpk;
+                self.z = *z;
+            }
+
+            /// The expanded public key, for encapsulating to this key
+            pub fn public_key(&self) -> &ExpandedPublicKey {
+                &self.pk
+            }
+        }
+
+        impl From<&SecretKey> for ExpandedSecretKey {
+            fn from(sk: &SecretKey) -> Self {
+                Self::new(sk)
+            }
+        }
+
+        /// Generate a new key pair
+        ///
+        /// With the `pct` feature, the new key pair is checked with
+        /// [`pairwise_consistency_test`] before it is returned.
+        pub fn keypair(
+            rng: &mut (impl CryptoRng + RngCore),
+        ) -> Result<(PublicKey, SecretKey), Error> {
+            let seed = DecapsulationKeySeed::generate(rng)?;
+            let sk = SecretKey::from_seed(&seed);
+            let pk = sk.public_key();
+            #[cfg(feature = "pct")]
+            pairwise_consistency_test(&pk, &sk, rng)?;
+            Ok((pk, sk))
+        }
+
+        /// Generate a new key pair with the secret key in seed form, which
+        /// takes only 64 bytes to store, see [`keypair`]
+        ///
+        /// Expand the seed with [`SecretKey::from_seed`] to decapsulate.
+        pub fn keypair_seed(
+            rng: &mut (impl CryptoRng + RngCore),
+        ) -> Result<(PublicKey, DecapsulationKeySeed), Error> {
+            let seed = DecapsulationKeySeed::generate(rng)?;
+            let sk = SecretKey::from_seed(&seed);
+            let pk = sk.public_key();
+            #[cfg(feature = "pct")]
+            pairwise_consistency_test(&pk, &sk, rng)?;
+            Ok((pk, seed))
+        }
+
+        /// Generate a new key pair with the secret key both expanded and in
+        /// seed form, see [`keypair`]
+        pub fn keypair_seeded(
+            rng: &mut (impl CryptoRng + RngCore),
+        ) -> Result<(PublicKey, SeededSecretKey), Error> {
+            let sk = SeededSecretKey::from_seed(DecapsulationKeySeed::generate(rng)?);
+            let pk = sk.sk.public_key();
+            #[cfg(feature = "pct")]
+            pairwise_consistency_test(&pk, &sk.sk, rng)?;
+            Ok((pk, sk))
+        }
+
+        /// Generate a new key pair in heap allocations, see [`keypair`]
+        #[cfg(feature = "alloc")]
+        pub fn keypair_boxed(
+            rng: &mut (impl CryptoRng + RngCore),
+        ) -> Result<(Box<PublicKey>, Box<SecretKey>), Error> {
+            let seed = DecapsulationKeySeed::generate(rng)?;
+            let sk = SecretKey::from_seed_boxed(&seed);
+            let pk = sk.public_key_boxed();
+            #[cfg(feature = "pct")]
+            pairwise_consistency_test(&pk, &sk, rng)?;
+            Ok((pk, sk))
+        }
+
+        /// Generate a new key pair into every entry of `keys`, see [`keypair`]
+        ///
+        /// The keys are generated four at a time in lockstep, which shares
+        /// the sponge bookkeeping of SHA-3 and SHAKE between them. Keys left
+        /// over after the last group of four are generated one at a time,
+        /// as are all keys of the Kyber-90s schemes, which use AES and SHA-2
+        /// instead and gain nothing from this. With the `pct` feature, each
+        /// key pair is checked through an [`ExpandedSecretKey`], which
+        /// samples the matrix once instead of twice.
+        ///
+        /// Every entry of `keys` is overwritten. If an error is returned,
+        /// every entry is `None`.
+        pub fn keypair_batch(
+            rng: &mut (impl CryptoRng + RngCore),
+            keys: &mut [Option<(PublicKey, SecretKey)>],
+        ) -> Result<(), Error> {
+            let result = generate_batch(rng, keys);
+            if result.is_err() {
+                keys.fill_with(|| None);
+            }
+            result
+        }
+
+        fn generate_batch(
+            rng: &mut (impl CryptoRng + RngCore),
+            keys: &mut [Option<(PublicKey, SecretKey)>],
+        ) -> Result<(), Error> {
+            if !$symmetric::FAST_X4 {
+                for key in keys {
+                    *key = Some(keypair(rng)?);
+                }
+                return Ok(());
+            }
+            let mut seeds = [[0u8; 2 * KYBER_SYMBYTES]; 4];
+            let mut chunks = keys.chunks_exact_mut(4);
+            let result = chunks.by_ref().try_for_each(|chunk| {
+                rng.try_fill_bytes(seeds.as_flattened_mut())
+                    .map_err(|_| Error::RngFailure)?;
+                let chunk: &mut [_; 4] = chunk.try_into().unwrap();
+                let mut keys = chunk
+                    .each_mut()
+                    .map(|key| key.insert((PublicKey::zero(), SecretKey::zero())));
+                SecretKey::expand_x4(keys.each_mut().map(|(_, sk)| sk), seeds.each_ref());
+                for (pk, sk) in keys {
+                    *pk = sk.public_key();
+                    #[cfg(feature = "pct")]
+                    {
+                        let expanded = ExpandedSecretKey::new(sk);
+                        let (ct, ss) = encaps_expanded(expanded.public_key(), rng)?;
+                        if !bool::from(ss.ct_eq(&decaps_expanded(&ct, &expanded))) {
+                            return Err(Error::PairwiseConsistency);
+                        }
+                    }
+                }
+                Ok(())
+            });
+            wipe(seeds.as_flattened_mut());
+            result?;
+            for key in chunks.into_remainder() {
+                *key = Some(keypair(rng)?);
+            }
+            Ok(())
+        }
+
+        /// Check that the public and secret key belong together by running
+        /// encapsulation and decapsulation once
+        pub fn pairwise_consistency_test(
+            pk: &PublicKey,
+            sk: &SecretKey,
+            rng: &mut (impl CryptoRng + RngCore),
+        ) -> Result<(), Error> {
+            let (ct, ss) = encaps(pk, rng)?;
+            if !bool::from(ss.ct_eq(&decaps(&ct, sk))) {
+                return Err(Error::PairwiseConsistency);
+            }
+            Ok(())
+        }
+
+        /// Deterministically derive a key pair from the seeds `d` and `z`
+        ///
+        /// The seeds must be uniformly random and kept secret: they fully
+        /// determine the secret key. This is meant for known-answer tests
+        /// and for callers that provide their own source of entropy.
+        pub fn keypair_derand(
+            d: &[u8; KYBER_SYMBYTES],
+            z: &[u8; KYBER_SYMBYTES],
+        ) -> (PublicKey, SecretKey) {
+            let mut sk = SecretKey::zero();
+            sk.expand(d, z);
+            (sk.public_key(), sk)
+        }
+
+        /// Generate a ciphertext and shared secret for the public key
+        pub fn encaps(
+            pk: &PublicKey,
+            rng: &mut (impl CryptoRng + RngCore),
+        ) -> Result<(Ciphertext, SharedSecret), Error> {
+            let mut ct = Ciphertext([0; CT_SIZE]);
+            let hpk = $symmetric::hash_h(&pk.0 .0);
+            let ss = encaps_into(IndcpaPublicKey::Packed(&pk.0 .0), &hpk, rng, &mut ct)?;
+            Ok((ct, ss))
+        }
+
+        /// Generate a heap-allocated ciphertext and a shared secret for the
+        /// public key, see [`encaps`]
+        #[cfg(feature = "alloc")]
+        pub fn encaps_boxed(
+            pk: &PublicKey,
+            rng: &mut (impl CryptoRng + RngCore),
+        ) -> Result<(Box<Ciphertext>, SharedSecret), Error> {
+            let mut ct = Box::new(Ciphertext([0; CT_SIZE]));
+            let hpk = $symmetric::hash_h(&pk.0 .0);
+            let ss = encaps_into(IndcpaPublicKey::Packed(&pk.0 .0), &hpk, rng, &mut ct)?;
+            Ok((ct, ss))
+        }
+
+        /// Generate a ciphertext and shared secret for an expanded public
+        /// key, see [`ExpandedPublicKey`]
+        ///
+        /// The result is the same as that of [`encaps`] with the original key.
+        pub fn encaps_expanded(
+            pk: &ExpandedPublicKey,
+            rng: &mut (impl CryptoRng + RngCore),
+        ) -> Result<(Ciphertext, SharedSecret), Error> {
+            let mut ct = Ciphertext([0; CT_SIZE]);
+            let ss = encaps_into(IndcpaPublicKey::Expanded(&pk.key), &pk.hpk, rng, &mut ct)?;
+            Ok((ct, ss))
+        }
+
+        /// Generate `n` ciphertexts and shared secrets for the public key,
+        /// see [`encaps`]
+        ///
+        /// The public key is expanded once, into a heap allocation, so the
+        /// matrix A^T is only sampled once for the whole batch. The seeds
+        /// are drawn from `rng` in one call per 16 encapsulations.
+        #[cfg(feature = "alloc")]
+        pub fn encaps_batch(
+            pk: &PublicKey,
+            rng: &mut (impl CryptoRng + RngCore),
+            n: usize,
+        ) -> Result<Vec<(Ciphertext, SharedSecret)>, Error> {
+            let expanded = ExpandedPublicKey::new_boxed(pk);
+            let indcpa_pk = IndcpaPublicKey::Expanded(&expanded.key);
+            let mut out = Vec::with_capacity(n);
+            let mut seeds = [0u8; 16 * KYBER_SYMBYTES];
+            while out.len() < n {
+                let count = (n - out.len()).min(16);
+                let seeds = &mut seeds[..count * KYBER_SYMBYTES];
+                if rng.try_fill_bytes(seeds).is_err() {
+                    wipe(seeds);
+                    return Err(Error::RngFailure);
+                }
+                for m in seeds.chunks_exact(KYBER_SYMBYTES) {
+                    let mut ct = Ciphertext([0; CT_SIZE]);
+                    let m = m.try_into().unwrap();
+                    let hpk = &expanded.hpk;
+                    let ss = $enc::<$params, $symmetric>(indcpa_pk, hpk, m, (&mut ct.0).into());
+                    out.push((ct, ss));
+                }
+            }
+            wipe(&mut seeds);
+            Ok(out)
+        }
+
+        fn encaps_into(
+            pk: IndcpaPublicKey<'_, $params>,
+            hpk: &[u8; KYBER_SYMBYTES],
+            rng: &mut (impl CryptoRng + RngCore),
+            ct: &mut Ciphertext,
+        ) -> Result<SharedSecret, Error> {
+            let mut m = [0u8; KYBER_SYMBYTES];
+            rng.try_fill_bytes(&mut m).map_err(|_| Error::RngFailure)?;
+            let ss = $enc::<$params, $symmetric>(pk, hpk, &m, (&mut ct.0).into());
+            wipe(&mut m);
+            Ok(ss)
+        }
+
+        /// Deterministically generate a ciphertext and shared secret for the
+        /// public key from the seed `m`
+        ///
+        /// The seed must be uniformly random and kept secret: it determines
+        /// the shared secret.
+        pub fn encaps_derand(
+            pk: &PublicKey,
+            m: &[u8; KYBER_SYMBYTES],
+        ) -> (Ciphertext, SharedSecret) {
+            let mut ct = Ciphertext([0; CT_SIZE]);
+            let hpk = $symmetric::hash_h(&pk.0 .0);
+            let pk = IndcpaPublicKey::Packed(&pk.0 .0);
+            let ss = $enc::<$params, $symmetric>(pk, &hpk, m, (&mut ct.0).into());
+            (ct, ss)
+        }
+
+        /// Deterministically generate a ciphertext and shared secret for an
+        /// expanded public key from the seed `m`, see [`encaps_derand`]
+        pub fn encaps_expanded_derand(
+            pk: &ExpandedPublicKey,
+            m: &[u8; KYBER_SYMBYTES],
+        ) -> (Ciphertext, SharedSecret) {
+            let mut ct = Ciphertext([0; CT_SIZE]);
+            let indcpa_pk = IndcpaPublicKey::Expanded(&pk.key);
+            let ss = $enc::<$params, $symmetric>(indcpa_pk, &pk.hpk, m, (&mut ct.0).into());
+            (ct, ss)
+        }
+
+        /// Recover the shared secret from a ciphertext
+        pub fn decaps(ct: &Ciphertext, sk: &SecretKey) -> SharedSecret {
+            let (indcpa_sk, indcpa_pk, hpk, z) = unpack_sk::<$params>(&sk.0);
+            let indcpa_sk = IndcpaSecretKey::Packed(indcpa_sk);
+            let indcpa_pk = IndcpaPublicKey::Packed(indcpa_pk);
+            $dec::<$params, $symmetric>((&ct.0).into(), indcpa_sk, indcpa_pk, hpk, z)
+        }
+
+        /// Recover the shared secret from a ciphertext with an expanded
+        /// secret key, see [`ExpandedSecretKey`]
+        pub fn decaps_expanded(ct: &Ciphertext, sk: &ExpandedSecretKey) -> SharedSecret {
+            let indcpa_sk = IndcpaSecretKey::Expanded(&sk.sk);
+            let indcpa_pk = IndcpaPublicKey::Expanded(&sk.pk.key);
+            $dec::<$params, $symmetric>((&ct.0).into(), indcpa_sk, indcpa_pk, &sk.pk.hpk, &sk.z)
+        }
+
+        /// The scheme as a [`kem::Kem`](::kem::Kem), with [`PublicKey`] as
+        /// its encapsulation key
+        #[cfg(feature = "kem")]
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct Kem;
+
+        #[cfg(feature = "kem")]
+        impl ::kem::Kem for Kem {
+            type DecapsulationKey = DecapsulationKey;
+            type EncapsulationKey = PublicKey;
+            type SharedKeySize = ::kem::consts::U32;
+            type CiphertextSize = <$params as ParameterSet>::IndcpaBytes;
+        }
+
+        /// Decapsulation key of [`Kem`]: a [`SeededSecretKey`] together
+        /// with its public key
+        ///
+        /// It is created from and exported to its seed with
+        /// [`KeyInit`](::kem::KeyInit) and [`KeyExport`](::kem::KeyExport).
+        /// Unlike [`keypair`], generating it never runs the pairwise
+        /// consistency test.
+        #[cfg(feature = "kem")]
+        pub struct DecapsulationKey {
+            sk: SeededSecretKey,
+            pk: PublicKey,
+        }
+
+        #[cfg(feature = "kem")]
+        impl DecapsulationKey {
+            /// The secret key, for the functions of this module
+            pub fn secret_key(&self) -> &SecretKey {
+                self.sk.secret_key()
+            }
+        }
+
+        #[cfg(feature = "kem")]
+        impl From<SeededSecretKey> for DecapsulationKey {
+            fn from(sk: SeededSecretKey) -> Self {
+                let pk = sk.sk.public_key();
+                DecapsulationKey { sk, pk }
+            }
+        }
+
+        #[cfg(feature = "kem")]
+        impl ::kem::KeySizeUser for DecapsulationKey {
+            type KeySize = ::kem::consts::U64;
+        }
+
+        #[cfg(feature = "kem")]
+        impl ::kem::KeyInit for DecapsulationKey {
+            fn new(seed: &::kem::Key<Self>) -> Self {
+                SeededSecretKey::from_seed(DecapsulationKeySeed(seed.0)).into()
+            }
+        }
+
+        #[cfg(feature = "kem")]
+        impl ::kem::KeyExport for DecapsulationKey {
+            fn to_bytes(&self) -> ::kem::Key<Self> {
+                Array(self.sk.seed.0)
+            }
+        }
+
+        #[cfg(feature = "kem")]
+        impl ::kem::Generate for DecapsulationKey {
+            fn try_generate_from_rng<R>(rng: &mut R) -> Result<Self, R::Error>
+            where
+                R: ::kem::common::rand_core::TryCryptoRng + ?Sized,
+            {
+                let mut seed = DecapsulationKeySeed([0; 2 * KYBER_SYMBYTES]);
+                rng.try_fill_bytes(&mut seed.0)?;
+                Ok(SeededSecretKey::from_seed(seed).into())
+            }
+        }
+
+        #[cfg(feature = "kem")]
+        impl ::kem::Decapsulator for DecapsulationKey {
+            type Kem = Kem;
+
+            fn encapsulation_key(&self) -> &PublicKey {
+                &self.pk
+            }
+        }
+
+        #[cfg(feature = "kem")]
+        impl ::kem::Decapsulate for DecapsulationKey {
+            fn decapsulate(&self, ct: &::kem::Ciphertext<Kem>) -> ::kem::SharedKey<Kem> {
+                let ss = decaps(&Ciphertext(ct.0), self.secret_key());
+                Array(ss.into_bytes())
+            }
+        }
+
+        #[cfg(feature = "kem")]
+        impl ::kem::KeySizeUser for PublicKey {
+            type KeySize = <$params as ParameterSet>::IndcpaPkBytes;
+        }
+
+        #[cfg(feature = "kem")]
+        impl ::kem::TryKeyInit for PublicKey {
+            fn new(bytes: &::kem::Key<Self>) -> Result<Self, ::kem::InvalidKey> {
+                PublicKey::from_bytes(bytes.0).map_err(|_| ::kem::InvalidKey)
+            }
+        }
+
+        #[cfg(feature = "kem")]
+        impl ::kem::KeyExport for PublicKey {
+            fn to_bytes(&self) -> ::kem::Key<Self> {
+                self.0 .0.clone()
+            }
+        }
+
+        #[cfg(feature = "kem")]
+        impl ::kem::Encapsulate for PublicKey {
+            type Kem = Kem;
+
+            fn encapsulate_with_rng<R>(
+                &self,
+                rng: &mut R,
+            ) -> (::kem::Ciphertext<Kem>, ::kem::SharedKey<Kem>)
+            where
+                R: ::kem::common::rand_core::CryptoRng + ?Sized,
+            {
+                let mut m = [0; KYBER_SYMBYTES];
+                rng.fill_bytes(&mut m);
+                let (ct, ss) = encaps_derand(self, &m);
+                wipe(&mut m);
+                (Array(ct.0), Array(ss.into_bytes()))
+            }
+        }
+    };
+}
+
+/// Kyber512, targeting NIST security level 1
 pub mod kyber512 {
-    use crate::indcpa::indcpa_keypair;
+    kyber_kem!(Params512, Fips202, false, kyber_enc, kyber_dec);
+}
+
+/// Kyber768, targeting NIST security level 3
+pub mod kyber768 {
+    kyber_kem!(Params768, Fips202, false, kyber_enc, kyber_dec);
+}
+
+/// Kyber1024, targeting NIST security level 5
+pub mod kyber1024 {
+    kyber_kem!(Params1024, Fips202, false, kyber_enc, kyber_dec);
+}
+
+/// Kyber512-90s, which uses SHA-2 and AES-256-CTR instead of SHA-3 and SHAKE
+pub mod kyber512_90s {
+    kyber_kem!(Params512, Nineties, false, kyber_enc, kyber_dec);
+}
+
+/// Kyber768-90s, which uses SHA-2 and AES-256-CTR instead of SHA-3 and SHAKE
+pub mod kyber768_90s {
+    kyber_kem!(Params768, Nineties, false, kyber_enc, kyber_dec);
+}
+
+/// Kyber1024-90s, which uses SHA-2 and AES-256-CTR instead of SHA-3 and SHAKE
+pub mod kyber1024_90s {
+    kyber_kem!(Params1024, Nineties, false, kyber_enc, kyber_dec);
+}
+
+/// ML-KEM-512 as standardized in FIPS 203, targeting NIST security level 1
+pub mod ml_kem_512 {
+    kyber_kem!(Params512, Fips202, true, mlkem_enc, mlkem_dec);
+}
+
+/// ML-KEM-768 as standardized in FIPS 203, targeting NIST security level 3
+pub mod ml_kem_768 {
+    kyber_kem!(Params768, Fips202, true, mlkem_enc, mlkem_dec);
+}
+
+/// ML-KEM-1024 as standardized in FIPS 203, targeting NIST security level 5
+pub mod ml_kem_1024 {
+    kyber_kem!(Params1024, Fips202, true, mlkem_enc, mlkem_dec);
+}
+
+pub mod dynamic;
 
+#[cfg(test)]
+mod test {
     use super::*;
+    use crate::utils::random_array;
+    use rand::{rngs::StdRng, thread_rng, SeedableRng};
+    use std::{format, string::String, vec::Vec};
+
+    fn check_roundtrip<P: ParameterSet, S: Symmetric>() {
+        let mut sk = KyberSecretKey(Array::default());
+        kem_keypair::<P, S>(&random_array(), &random_array(), false, &mut sk);
+        let (indcpa_sk, indcpa_pk, hpk, z) = unpack_sk(&sk);
+        let (sk, pk) = (
+            IndcpaSecretKey::Packed(indcpa_sk),
+            IndcpaPublicKey::Packed(indcpa_pk),
+        );
+        let mut ct = Array::default();
+        let ss = kyber_enc::<P, S>(pk, hpk, &random_array(), &mut ct);
+        assert_eq!(
+            ss.as_bytes(),
+            kyber_dec::<P, S>(&ct, sk, pk, hpk, z).as_bytes()
+        );
+
+        // a modified ciphertext is implicitly rejected
+        ct[0] ^= 1;
+        assert_ne!(
+            ss.as_bytes(),
+            kyber_dec::<P, S>(&ct, sk, pk, hpk, z).as_bytes()
+        );
+
+        let mut sk = KyberSecretKey(Array::default());
+        kem_keypair::<P, S>(&random_array(), &random_array(), true, &mut sk);
+        let (indcpa_sk, indcpa_pk, hpk, z) = unpack_sk(&sk);
+        let (sk, pk) = (
+            IndcpaSecretKey::Packed(indcpa_sk),
+            IndcpaPublicKey::Packed(indcpa_pk),
+        );
+        let mut ct = Array::default();
+        let ss = mlkem_enc::<P, S>(pk, hpk, &random_array(), &mut ct);
+        assert_eq!(
+            ss.as_bytes(),
+            mlkem_dec::<P, S>(&ct, sk, pk, hpk, z).as_bytes()
+        );
+
+        ct[0] ^= 1;
+        assert_ne!(
+            ss.as_bytes(),
+            mlkem_dec::<P, S>(&ct, sk, pk, hpk, z).as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        check_roundtrip::<Params512, Fips202>();
+        check_roundtrip::<Params768, Fips202>();
+        check_roundtrip::<Params1024, Fips202>();
+        check_roundtrip::<Params512, Nineties>();
+        check_roundtrip::<Params768, Nineties>();
+        check_roundtrip::<Params1024, Nineties>();
+    }
+
+    #[test]
+    fn test_kyber512() {
+        let (pk, sk) = kyber512::keypair(&mut thread_rng()).unwrap();
+        let (ct, ss) = kyber512::encaps(&pk, &mut thread_rng()).unwrap();
+        assert_eq!(ss.as_bytes(), kyber512::decaps(&ct, &sk).as_bytes());
+    }
+
+    #[test]
+    fn test_kyber768() {
+        let (pk, sk) = kyber768::keypair(&mut thread_rng()).unwrap();
+        let (ct, ss) = kyber768::encaps(&pk, &mut thread_rng()).unwrap();
+        assert_eq!(ss.as_bytes(), kyber768::decaps(&ct, &sk).as_bytes());
+    }
+
+    #[test]
+    fn test_kyber1024() {
+        let (pk, sk) = kyber1024::keypair(&mut thread_rng()).unwrap();
+        let (ct, ss) = kyber1024::encaps(&pk, &mut thread_rng()).unwrap();
+        assert_eq!(ss.as_bytes(), kyber1024::decaps(&ct, &sk).as_bytes());
+    }
+
+    #[test]
+    fn test_kyber_90s() {
+        let (pk, sk) = kyber512_90s::keypair(&mut thread_rng()).unwrap();
+        let (ct, ss) = kyber512_90s::encaps(&pk, &mut thread_rng()).unwrap();
+        assert_eq!(ss.as_bytes(), kyber512_90s::decaps(&ct, &sk).as_bytes());
+
+        let (pk, sk) = kyber768_90s::keypair(&mut thread_rng()).unwrap();
+        let (ct, ss) = kyber768_90s::encaps(&pk, &mut thread_rng()).unwrap();
+        assert_eq!(ss.as_bytes(), kyber768_90s::decaps(&ct, &sk).as_bytes());
+
+        let (pk, sk) = kyber1024_90s::keypair(&mut thread_rng()).unwrap();
+        let (ct, ss) = kyber1024_90s::encaps(&pk, &mut thread_rng()).unwrap();
+        assert_eq!(ss.as_bytes(), kyber1024_90s::decaps(&ct, &sk).as_bytes());
+        assert_eq!(sk.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_ml_kem() {
+        let (pk, sk) = ml_kem_512::keypair(&mut thread_rng()).unwrap();
+        let (ct, ss) = ml_kem_512::encaps(&pk, &mut thread_rng()).unwrap();
+        assert_eq!(ss.as_bytes(), ml_kem_512::decaps(&ct, &sk).as_bytes());
+
+        let (pk, sk) = ml_kem_768::keypair(&mut thread_rng()).unwrap();
+        let (ct, ss) = ml_kem_768::encaps(&pk, &mut thread_rng()).unwrap();
+        assert_eq!(ss.as_bytes(), ml_kem_768::decaps(&ct, &sk).as_bytes());
+
+        let (pk, sk) = ml_kem_1024::keypair(&mut thread_rng()).unwrap();
+        let (ct, ss) = ml_kem_1024::encaps(&pk, &mut thread_rng()).unwrap();
+        assert_eq!(ss.as_bytes(), ml_kem_1024::decaps(&ct, &sk).as_bytes());
+    }
+
+    #[test]
+    fn test_caller_rng() {
+        let (pk, sk) = ml_kem_512::keypair(&mut StdRng::seed_from_u64(1)).unwrap();
+        let (pk2, _) = ml_kem_512::keypair(&mut StdRng::seed_from_u64(1)).unwrap();
+        assert_eq!(pk.as_bytes(), pk2.as_bytes());
+
+        let (ct, ss) = ml_kem_512::encaps(&pk, &mut StdRng::seed_from_u64(2)).unwrap();
+        let (ct2, ss2) = ml_kem_512::encaps(&pk, &mut StdRng::seed_from_u64(2)).unwrap();
+        assert_eq!(ct.as_bytes(), ct2.as_bytes());
+        assert_eq!(ss.as_bytes(), ss2.as_bytes());
+        assert_eq!(ss.as_bytes(), ml_kem_512::decaps(&ct, &sk).as_bytes());
+    }
+
+    #[test]
+    fn test_seed_roundtrip() {
+        let seed = DecapsulationKeySeed::generate(&mut thread_rng()).unwrap();
+        let sk = ml_kem_768::SecretKey::from_seed(&seed);
+        assert_eq!(sk.0 .0, ml_kem_768::SecretKey::from_seed(&seed).0 .0);
+
+        let pk = sk.public_key();
+        let (ct, ss) = ml_kem_768::encaps(&pk, &mut thread_rng()).unwrap();
+        assert_eq!(ss.as_bytes(), ml_kem_768::decaps(&ct, &sk).as_bytes());
+
+        let (pk, seed) = kyber512::keypair_seed(&mut thread_rng()).unwrap();
+        let sk = kyber512::SecretKey::from_seed(&seed);
+        assert_eq!(pk.0 .0, sk.public_key().0 .0);
+        let (ct, ss) = kyber512::encaps(&pk, &mut thread_rng()).unwrap();
+        assert_eq!(ss.as_bytes(), kyber512::decaps(&ct, &sk).as_bytes());
 
-    const K: usize = 2;
-    const PK_BYTES: usize = K * KYBER_POLYBYTES * KYBER_SYMBYTES;
-    const SK_BYTES: usize = K * KYBER_POLYBYTES;
-    const CT_SIZE: usize = kyber_polyvec_compressed_bytes::<K>() + kyber_poly_compressed_bytes::<K>();
+        // the same randomness gives the same key as keypair
+        let (pk, _) = kyber512::keypair_seed(&mut StdRng::seed_from_u64(5)).unwrap();
+        let (pk2, _) = kyber512::keypair(&mut StdRng::seed_from_u64(5)).unwrap();
+        assert_eq!(pk.as_bytes(), pk2.as_bytes());
 
-    pub struct PublicKey(KyberPublicKey<PK_BYTES>);
-    pub struct SecretKey(KyberSecretKey<SK_BYTES>);
+        // a seeded key can be converted back to its seed
+        let (pk, sk) = kyber768::keypair_seeded(&mut StdRng::seed_from_u64(6)).unwrap();
+        let (pk2, sk2) = kyber768::keypair(&mut StdRng::seed_from_u64(6)).unwrap();
+        assert_eq!(pk.as_bytes(), pk2.as_bytes());
+        assert_eq!(sk.secret_key().as_bytes(), sk2.as_bytes());
+        let (ct, ss) = kyber768::encaps(&pk, &mut thread_rng()).unwrap();
+        assert_eq!(
+            ss.as_bytes(),
+            kyber768::decaps(&ct, sk.secret_key()).as_bytes()
+        );
+        let (sk, seed) = sk.into_parts();
+        let sk3 = kyber768::SeededSecretKey::from(seed.clone());
+        assert_eq!(sk3.secret_key().as_bytes(), sk.as_bytes());
+        assert!(bool::from(sk3.seed().ct_eq(&seed)));
+    }
+
+    /// `thread_rng` for the `kem` traits, which take a newer `rand_core`
+    #[cfg(feature = "kem")]
+    struct KemRng(rand::rngs::ThreadRng);
+
+    #[cfg(feature = "kem")]
+    impl ::kem::common::rand_core::TryRng for KemRng {
+        type Error = core::convert::Infallible;
+
+        fn try_next_u32(&mut self) -> Result<u32, core::convert::Infallible> {
+            Ok(self.0.next_u32())
+        }
+
+        fn try_next_u64(&mut self) -> Result<u64, core::convert::Infallible> {
+            Ok(self.0.next_u64())
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), core::convert::Infallible> {
+            self.0.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "kem")]
+    impl ::kem::common::rand_core::TryCryptoRng for KemRng {}
+
+    #[cfg(feature = "kem")]
+    fn check_kem_traits<K>()
+    where
+        K: ::kem::Kem,
+        K::DecapsulationKey: ::kem::KeyInit + ::kem::KeyExport + ::kem::Decapsulate,
+    {
+        use ::kem::{
+            Decapsulate, Decapsulator, Encapsulate, EncapsulationKey, FromSeed, KeyExport,
+            TryKeyInit,
+        };
+
+        let mut rng = KemRng(thread_rng());
+        let (dk, ek) = K::generate_keypair_from_rng(&mut rng);
+        let (ct, ss) = ek.encapsulate_with_rng(&mut rng);
+        assert_eq!(ss, dk.decapsulate(&ct));
+
+        let ek2 = EncapsulationKey::<K>::new(&ek.to_bytes()).unwrap();
+        assert!(ek2 == ek);
+        let (dk2, ek2) = K::from_seed(&dk.to_bytes());
+        assert!(ek2 == ek && dk2.encapsulation_key() == &ek);
+        assert_eq!(ss, dk2.decapsulate(&ct));
+    }
+
+    #[cfg(feature = "kem")]
+    #[test]
+    fn test_kem_traits() {
+        check_kem_traits::<kyber512::Kem>();
+        check_kem_traits::<kyber768::Kem>();
+        check_kem_traits::<kyber1024::Kem>();
+        check_kem_traits::<kyber768_90s::Kem>();
+        check_kem_traits::<ml_kem_512::Kem>();
+        check_kem_traits::<ml_kem_768::Kem>();
+        check_kem_traits::<ml_kem_1024::Kem>();
+    }
+
+    #[test]
+    fn test_byte_roundtrip() {
+        let (pk, sk) = kyber768::keypair(&mut thread_rng()).unwrap();
+        let (ct, ss) = kyber768::encaps(&pk, &mut thread_rng()).unwrap();
+        assert_eq!(pk.as_bytes().len(), kyber768::PublicKey::LEN);
+        assert_eq!(sk.as_bytes().len(), kyber768::SecretKey::LEN);
+        assert_eq!(ct.as_bytes().len(), kyber768::Ciphertext::LEN);
+
+        let pk = kyber768::PublicKey::try_from(&pk.into_bytes()[..]).unwrap();
+        let sk = kyber768::SecretKey::try_from(&sk.into_bytes()[..]).unwrap();
+        let ct = kyber768::Ciphertext::try_from(&ct.into_bytes()[..]).unwrap();
+        let ss = SharedSecret::try_from(&ss.into_bytes()[..]).unwrap();
+        assert_eq!(ss.as_bytes(), kyber768::decaps(&ct, &sk).as_bytes());
+        assert_eq!(
+            kyber768::encaps_derand(&pk, &[0; KYBER_SYMBYTES])
+                .1
+                .as_bytes(),
+            kyber768::encaps_derand(&sk.public_key(), &[0; KYBER_SYMBYTES])
+                .1
+                .as_bytes()
+        );
+
+        assert_eq!(
+            kyber768::SecretKey::try_from(&[0u8; 10][..]).err(),
+            Some(Error::InvalidKeyLength)
+        );
+        assert_eq!(
+            kyber768::Ciphertext::try_from(&[0u8; 10][..]).err(),
+            Some(Error::InvalidCiphertextLength)
+        );
+        assert_eq!(
+            SharedSecret::try_from(&[0u8; 10][..]).err(),
+            Some(Error::InvalidSharedSecretLength)
+        );
+
+        let seed = DecapsulationKeySeed::generate(&mut thread_rng()).unwrap();
+        let bytes = seed.clone().into_bytes();
+        let parsed = DecapsulationKeySeed::try_from(&bytes[..]).unwrap();
+        assert!(bool::from(parsed.ct_eq(&seed)));
+        assert_eq!(
+            DecapsulationKeySeed::try_from(&bytes[1..]).err(),
+            Some(Error::InvalidKeyLength)
+        );
+    }
+
+    macro_rules! check_expanded {
+        ($module: ident) => {{
+            let (pk, sk) = $module::keypair(&mut thread_rng()).unwrap();
+            let expanded = $module::ExpandedPublicKey::new(&pk);
+            for _ in 0..3 {
+                let m = random_array();
+                let (ct, ss) = $module::encaps_derand(&pk, &m);
+                let (ct2, ss2) = $module::encaps_expanded_derand(&expanded, &m);
+                assert_eq!(ct.as_bytes(), ct2.as_bytes());
+                assert_eq!(ss.as_bytes(), ss2.as_bytes());
+            }
+            let (ct, ss) = $module::encaps_expanded(&expanded, &mut thread_rng()).unwrap();
+            assert_eq!(ss.as_bytes(), $module::decaps(&ct, &sk).as_bytes());
+
+            let expanded_sk = $module::ExpandedSecretKey::new(&sk);
+            assert_eq!(
+                ss.as_bytes(),
+                $module::decaps_expanded(&ct, &expanded_sk).as_bytes()
+            );
+
+            // implicit rejection gives the same result as with the packed key
+            let mut bytes = ct.into_bytes();
+            bytes[0] ^= 1;
+            let ct = $module::Ciphertext::from_bytes(bytes);
+            assert_ne!(
+                ss.as_bytes(),
+                $module::decaps_expanded(&ct, &expanded_sk).as_bytes()
+            );
+            assert_eq!(
+                $module::decaps(&ct, &sk).as_bytes(),
+                $module::decaps_expanded(&ct, &expanded_sk).as_bytes()
+            );
+        }};
+    }
+
+    #[test]
+    fn test_expanded_keys() {
+        check_expanded!(kyber512);
+        check_expanded!(kyber768);
+        check_expanded!(kyber1024);
+        check_expanded!(kyber512_90s);
+        check_expanded!(kyber1024_90s);
+        check_expanded!(ml_kem_512);
+        check_expanded!(ml_kem_768);
+        check_expanded!(ml_kem_1024);
+    }
+
+    /// An RNG that fails once it would give out more than `left` bytes
+    struct FailingRng {
+        left: usize,
+    }
+
+    impl RngCore for FailingRng {
+        fn next_u32(&mut self) -> u32 {
+            let mut bytes = [0; 4];
+            self.fill_bytes(&mut bytes);
+            u32::from_le_bytes(bytes)
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            let mut bytes = [0; 8];
+            self.fill_bytes(&mut bytes);
+            u64::from_le_bytes(bytes)
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            self.try_fill_bytes(dest).unwrap()
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+            self.left = self.left.checked_sub(dest.len()).ok_or_else(|| {
+                core::num::NonZeroU32::new(rand_core::Error::CUSTOM_START).unwrap()
+            })?;
+            dest.fill(7);
+            Ok(())
+        }
+    }
+
+    impl CryptoRng for FailingRng {}
+
+    #[test]
+    fn test_keypair_batch() {
+        // one group of four keys and two more
+        let mut keys: [Option<_>; 6] = Default::default();
+        kyber768::keypair_batch(&mut thread_rng(), &mut keys).unwrap();
+        for (i, key) in keys.iter().enumerate() {
+            let (pk, sk) = key.as_ref().unwrap();
+            assert_eq!(pk.as_bytes(), sk.public_key().as_bytes());
+            sk.validate().unwrap();
+            let (ct, ss) = kyber768::encaps(pk, &mut thread_rng()).unwrap();
+            assert_eq!(ss.as_bytes(), kyber768::decaps(&ct, sk).as_bytes());
+            let (other, _) = keys[(i + 1) % keys.len()].as_ref().unwrap();
+            assert_ne!(pk.as_bytes(), other.as_bytes());
+        }
+
+        // if the RNG fails part of the way through, no keys are left behind
+        let result = kyber768::keypair_batch(&mut FailingRng { left: 300 }, &mut keys);
+        assert_eq!(result.err(), Some(Error::RngFailure));
+        assert!(keys.iter().all(Option::is_none));
+
+        // the same seeds give the same keys as keypair_derand
+        macro_rules! check_seeds {
+            ($module: ident) => {
+                let mut keys: [Option<_>; 4] = Default::default();
+                $module::keypair_batch(&mut StdRng::seed_from_u64(3), &mut keys).unwrap();
+                let mut rng = StdRng::seed_from_u64(3);
+                for key in &keys {
+                    let (pk, sk) = key.as_ref().unwrap();
+                    let seed = DecapsulationKeySeed::generate(&mut rng).unwrap();
+                    let (pk2, sk2) = $module::keypair_derand(seed.d(), seed.z());
+                    assert_eq!(pk.as_bytes(), pk2.as_bytes());
+                    assert_eq!(sk.as_bytes(), sk2.as_bytes());
+                }
+            };
+        }
+        check_seeds!(kyber512);
+        check_seeds!(ml_kem_1024);
+        // the Kyber-90s keys are generated one at a time, so the pairwise
+        // consistency test draws from the RNG between their seeds
+        #[cfg(not(feature = "pct"))]
+        check_seeds!(kyber768_90s);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_encaps_batch() {
+        let (pk, sk) = ml_kem_1024::keypair(&mut thread_rng()).unwrap();
+        let batch = ml_kem_1024::encaps_batch(&pk, &mut thread_rng(), 20).unwrap();
+        assert_eq!(batch.len(), 20);
+        for (ct, ss) in &batch {
+            assert_eq!(ss.as_bytes(), ml_kem_1024::decaps(ct, &sk).as_bytes());
+        }
+        assert_ne!(batch[0].1.as_bytes(), batch[1].1.as_bytes());
+        assert!(ml_kem_1024::encaps_batch(&pk, &mut thread_rng(), 0)
+            .unwrap()
+            .is_empty());
+
+        // the same seeds give the same ciphertexts as encaps
+        let batch = ml_kem_1024::encaps_batch(&pk, &mut StdRng::seed_from_u64(4), 2).unwrap();
+        let (ct, ss) = ml_kem_1024::encaps(&pk, &mut StdRng::seed_from_u64(4)).unwrap();
+        assert_eq!(batch[0].0.as_bytes(), ct.as_bytes());
+        assert_eq!(batch[0].1.as_bytes(), ss.as_bytes());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_boxed() {
+        let (pk, sk) = ml_kem_1024::keypair_boxed(&mut thread_rng()).unwrap();
+        let (ct, ss) = ml_kem_1024::encaps_boxed(&pk, &mut thread_rng()).unwrap();
+        assert_eq!(ss.as_bytes(), ml_kem_1024::decaps(&ct, &sk).as_bytes());
+
+        let pk2 = ml_kem_1024::PublicKey::try_from_bytes_boxed(pk.as_bytes()).unwrap();
+        let sk2 = ml_kem_1024::SecretKey::try_from_bytes_boxed(sk.as_bytes()).unwrap();
+        let ct2 = ml_kem_1024::Ciphertext::try_from_bytes_boxed(ct.as_bytes()).unwrap();
+        assert_eq!(pk2.as_bytes(), pk.as_bytes());
+        assert_eq!(ss.as_bytes(), ml_kem_1024::decaps(&ct2, &sk2).as_bytes());
+
+        let seed = DecapsulationKeySeed::generate(&mut thread_rng()).unwrap();
+        let sk3 = ml_kem_1024::SecretKey::from_seed_boxed(&seed);
+        assert_eq!(
+            sk3.as_bytes(),
+            ml_kem_1024::SecretKey::from_seed(&seed).as_bytes()
+        );
+
+        let expanded = ml_kem_1024::ExpandedPublicKey::new_boxed(&pk);
+        let (ct3, ss3) = ml_kem_1024::encaps_expanded(&expanded, &mut thread_rng()).unwrap();
+        let expanded_sk = ml_kem_1024::ExpandedSecretKey::new_boxed(&sk);
+        assert_eq!(
+            ss3.as_bytes(),
+            ml_kem_1024::decaps_expanded(&ct3, &expanded_sk).as_bytes()
+        );
+
+        assert_eq!(
+            ml_kem_1024::PublicKey::try_from_bytes_boxed(&pk.as_bytes()[1..]).err(),
+            Some(Error::InvalidKeyLength)
+        );
+        assert_eq!(
+            ml_kem_1024::SecretKey::try_from_bytes_boxed(&sk.as_bytes()[1..]).err(),
+            Some(Error::InvalidKeyLength)
+        );
+        assert_eq!(
+            ml_kem_1024::Ciphertext::try_from_bytes_boxed(&ct.as_bytes()[1..]).err(),
+            Some(Error::InvalidCiphertextLength)
+        );
+    }
+
+    /// The stack every operation fits in, see the module documentation
+    const STACK_SIZE: usize = if cfg!(debug_assertions) {
+        128 * 1024
+    } else {
+        64 * 1024
+    };
+
+    fn run_with_stack(stack_size: usize, f: impl FnOnce() + Send + 'static) {
+        std::thread::Builder::new()
+            .stack_size(stack_size)
+            .spawn(f)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
 
-    pub fn keypair() -> Result<(PublicKey, SecretKey), ()> {
-        // let pk = PublicKey(KyberPublicKey([0; PK_BYTES]));
-        // let sk = SecretKey(KyberSecretKey([0; SK_BYTES]));
-        let _ = indcpa_keypair::<K>(&[0u8; KYBER_SYMBYTES]);
-        Err(())
+    /// Run the largest parameter sets on threads with the documented stack
+    /// sizes, which overflow into a guard page if they are too small
+    #[test]
+    fn test_small_stack() {
+        run_with_stack(STACK_SIZE, || {
+            let (pk, sk) = ml_kem_1024::keypair(&mut thread_rng()).unwrap();
+            let (ct, ss) = ml_kem_1024::encaps(&pk, &mut thread_rng()).unwrap();
+            assert_eq!(ss.as_bytes(), ml_kem_1024::decaps(&ct, &sk).as_bytes());
+
+            let (pk, sk) = kyber1024_90s::keypair(&mut thread_rng()).unwrap();
+            let (ct, ss) = kyber1024_90s::encaps(&pk, &mut thread_rng()).unwrap();
+            assert_eq!(ss.as_bytes(), kyber1024_90s::decaps(&ct, &sk).as_bytes());
+        });
+        run_with_stack(2 * STACK_SIZE, || {
+            // the keys are on the heap, so that only the work is on the stack
+            let mut keys: Vec<Option<_>> = (0..4).map(|_| None).collect();
+            ml_kem_1024::keypair_batch(&mut thread_rng(), &mut keys).unwrap();
+            let mut keys: Vec<Option<_>> = (0..4).map(|_| None).collect();
+            kyber1024_90s::keypair_batch(&mut thread_rng(), &mut keys).unwrap();
+        });
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_zeroize() {
+        use zeroize::Zeroize;
+
+        let (pk, mut sk) = kyber512::keypair(&mut thread_rng()).unwrap();
+        let (ct, mut ss) = kyber512::encaps(&pk, &mut thread_rng()).unwrap();
+        let mut expanded = kyber512::ExpandedSecretKey::new(&sk);
+        expanded.zeroize();
+        assert_ne!(
+            ss.as_bytes(),
+            kyber512::decaps_expanded(&ct, &expanded).as_bytes()
+        );
+        sk.zeroize();
+        ss.zeroize();
+        assert!(sk.as_bytes().iter().all(|&b| b == 0));
+        assert_eq!(ss.as_bytes(), &[0; KYBER_SSBYTES]);
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        let seed = DecapsulationKeySeed::generate(&mut thread_rng()).unwrap();
+        let sk = kyber512::SecretKey::from_seed(&seed);
+        let (_, other) = kyber512::keypair(&mut thread_rng()).unwrap();
+        assert!(bool::from(sk.ct_eq(&kyber512::SecretKey::from_seed(&seed))));
+        assert!(!bool::from(sk.ct_eq(&other)));
+
+        let ss = SharedSecret([1; KYBER_SSBYTES]);
+        assert!(bool::from(ss.ct_eq(&SharedSecret([1; KYBER_SSBYTES]))));
+        assert!(!bool::from(ss.ct_eq(&SharedSecret([2; KYBER_SSBYTES]))));
+        assert_eq!(format!("{:?}", ss), "SharedSecret(..)");
     }
 
-    pub fn encaps(_pk: &PublicKey) -> Result<(Ciphertext<CT_SIZE>, SharedSecret), ()> {
-        Err(())
+    #[test]
+    fn test_public_key_validation() {
+        let (pk, _) = ml_kem_512::keypair(&mut thread_rng()).unwrap();
+        let bytes = pk.0.as_ref().to_vec();
+        let parsed = ml_kem_512::PublicKey::try_from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.0 .0, pk.0 .0);
+
+        assert_eq!(
+            ml_kem_512::PublicKey::try_from_bytes(&bytes[1..]).err(),
+            Some(Error::InvalidKeyLength)
+        );
+
+        // the last coefficient of the first polynomial becomes 4095
+        let mut bytes = bytes;
+        bytes[KYBER_POLYBYTES - 1] = 0xff;
+        bytes[KYBER_POLYBYTES - 2] |= 0xf0;
+        assert_eq!(
+            ml_kem_512::PublicKey::try_from_bytes(&bytes).err(),
+            Some(Error::EncapsulationKeyModulusCheck)
+        );
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_secret_key_validation() {
+        let (pk, mut sk) = kyber768::keypair(&mut thread_rng()).unwrap();
+        assert_eq!(sk.validate(), Ok(()));
+        assert_eq!(
+            kyber768::pairwise_consistency_test(&pk, &sk, &mut thread_rng()),
+            Ok(())
+        );
+
+        let (other_pk, _) = kyber768::keypair(&mut thread_rng()).unwrap();
+        assert_eq!(
+            kyber768::pairwise_consistency_test(&other_pk, &sk, &mut thread_rng()),
+            Err(Error::PairwiseConsistency)
+        );
+
+        // corrupt the stored hash of the public key
+        sk.0 .0[Params768::SK_BYTES - 2 * KYBER_SYMBYTES] ^= 1;
+        assert_eq!(sk.validate(), Err(Error::DecapsulationKeyHashMismatch));
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Known-answer tests against the reference implementations
+    ///
+    /// The seeds are d = 0, 1, ..., 31, z = 32, ..., 63 and m = 64, ..., 95.
+    /// Keys and ciphertexts are compared through their SHA3-256 hash.
+    macro_rules! kat_test {
+        ($name: ident, $module: ident, $pk: expr, $sk: expr, $ct: expr, $ss: expr, $rejected: expr) => {
+            #[test]
+            fn $name() {
+                let d: [u8; KYBER_SYMBYTES] = std::array::from_fn(|i| i as u8);
+                let z: [u8; KYBER_SYMBYTES] = std::array::from_fn(|i| (i + 32) as u8);
+                let m: [u8; KYBER_SYMBYTES] = std::array::from_fn(|i| (i + 64) as u8);
+
+                let (pk, sk) = $module::keypair_derand(&d, &z);
+                assert_eq!(hex(&Fips202::hash_h(pk.0.as_ref())), $pk);
+                assert_eq!(hex(&Fips202::hash_h(sk.0.as_ref())), $sk);
+
+                let (mut ct, ss) = $module::encaps_derand(&pk, &m);
+                assert_eq!(hex(&Fips202::hash_h(&ct.0)), $ct);
+                assert_eq!(hex(&ss.0), $ss);
+                assert_eq!(ss.as_bytes(), $module::decaps(&ct, &sk).as_bytes());
+
+                ct.0[0] ^= 1;
+                assert_eq!(hex(&$module::decaps(&ct, &sk).0), $rejected);
+            }
+        };
+    }
+
+    kat_test!(
+        test_kat_kyber512,
+        kyber512,
+        "b0448d37ecbcf2918a73bcc1b3d8174e322df9863ce92f5ea0f6bd6bca7da05e",
+        "fce230daa8dd4f465ddcc2b8dc864d0c604983f97447e91524dde80ac913f522",
+        "961cdaa90d7f63ad4f72c696fc5e2d8d0abbf27bda1a3c6609d0789e1dfb3877",
+        "484c65aa18a6955f7a9f70137c882fcdbf0bd732d15ccf204a250bd17bf3fc4f",
+        "dc88ce8c295322d9bd8bfe68236db10dff156e145d253ff945a0c88b97376218"
+    );
+
+    kat_test!(
+        test_kat_kyber768,
+        kyber768,
+        "0df97a95f3e9fcaa29e04ffc0b246f17b50a19e74c90f88dadc41837dddcb6d1",
+        "157699f1afdbe4acff52ab00e6ef075bb6c267280d4c1e24fd907b43c9bf0ce5",
+        "3950acf029976ea4c229215284b32b6f4c3d75faea76c53912ce38ef59569604",
+        "7973130dd759b854824a18a0e046afd26cdd02ec874734200bc98d387965de7c",
+        "1f6f5151d7478ec9fe1fec0145f8df5e084f0497d82ef45aed4c280449e51a44"
+    );
+
+    kat_test!(
+        test_kat_kyber1024,
+        kyber1024,
+        "8db2bf117dfa02aaa1994384bbf77a1054cc601b1d3c6fb6b215823f55e6e7bf",
+        "ff10fb5eaff0ddf9bb5eaf2db484da4b33f918f025883ba98295372f2f54a18d",
+        "03b3120cada88f7882ae7fd1ee1383131765cc14cede25293bf2384d4e200ead",
+        "66cd15c09e372fe64522aea8c8086844999ce7f16565b4a043680bf0bc95083b",
+        "247095e137f21904fbf0f010384eed42ea287eae511aa033fb1a69d642a2b531"
+    );
+
+    kat_test!(
+        test_kat_kyber512_90s,
+        kyber512_90s,
+        "4f48bf478c07b48e493cc16e6e10cb85e5dd5c62e9ec1caf79d586b4ce793d0e",
+        "7d4ddb79b498a5c2dbc4e2703a4f55c5a9ff30b0be1e91e5e393a8986dc6fa08",
+        "6441b7ea9e15c7b4ba3841b6bc500aa3d5366541c490083e10081e82e1193405",
+        "a66a40f483dfd75e2331d0106ccc4877bcede7cb42560a9f4f836b8cf4f6722c",
+        "27f9456881ec549a350036cc9239feec327e26513a29eeaa377fbfcbdc692a7c"
+    );
+
+    kat_test!(
+        test_kat_kyber768_90s,
+        kyber768_90s,
+        "eded7de96ea95b6c625bdf1ab4c1e6cdcdce86c8648f09864422c3328d0d390d",
+        "0988a4d3001adf1d5c2ce74d90cd73cff5cd5cc8ae85482c1fce8c2787729a3a",
+        "5ec8b08ad39e259db7098fd69b32f0f19e67cd4229eb3c090e1c53a2a494bed3",
+        "ed5e28b65b0ba5c4d18f05f4dce888b91f2ff3684c8f4b2f095e4ee6ffeb8fcd",
+        "ee9f1d4f4e8fe182992bb4ab80d249a6cf4df892b42bdb8235006b30dade929c"
+    );
+
+    kat_test!(
+        test_kat_kyber1024_90s,
+        kyber1024_90s,
+        "f2a0f0750a6287da78399d673d9ea71a8bde230f20d1502ac95eae89a50b02ae",
+        "ec2f4c4d058335a42526a808b88c64b9f8ecc29ceb1421df2e0bf01cfb370037",
+        "2e48abfa0e353ff524d49335f86fd77552c620ba6941d7fc18cdf3df4c65201e",
+        "507b6137378fac0188cb04c3a7c94f7ddfbc2b0cb577095f2e538d076ee677d3",
+        "82bf8543739f68634d4a0101f39a0009f40d53b96923b3a1dece90429d5d565c"
+    );
+
+    kat_test!(
+        test_kat_ml_kem_512,
+        ml_kem_512,
+        "82f101ff648063b376e2bb6c5b7455f655a50c2feadade150efa0e0e6f365aea",
+        "0bd3f5df01098ac9c29d687c7f1bd0588a5573feeef8f1e3b4573fa7f6ab57c8",
+        "e3fdddb90255869185c07cdf1c1880b2efe08b6f04da4997b693c0dea61503bd",
+        "14cace3e48771b316676afad2cfcfe8488daaa4fad954e57236caa3f24a42cf7",
+        "32ee1fb3f7bd2915218e9c1b2d0d2da88f0edce6804278bab3a6123c5bb64fc4"
+    );
+
+    kat_test!(
+        test_kat_ml_kem_768,
+        ml_kem_768,
+        "a24e16d8f8f9383a95b77050f4d9fd2f5733eec1d63ef3c23ebf9918173669a7",
+        "1149f17c3c4ac6ab1e3e2d9d8bd0171355ac0fa31bb8855c48ceade874c0864b",
+        "b4cfbd24cef67afd3764276c6980e0f88f8e9ca57f59b7f12fe1a9c1e72f4710",
+        "9cddd089ffe70e3996e76f7c8d06746df34d07e8657bc0fcf2bb0e1c3084aea1",
+        "dcfc80c6db46ff7028e3a4398651c063ae7a42c107a6dc8cb07141861698ab92"
+    );
+
+    kat_test!(
+        test_kat_ml_kem_1024,
+        ml_kem_1024,
+        "61349e5c131a7e116a0463861d7d18663c5627c38c7147ddaadfd48acd7a4535",
+        "f0db5d938027fcd9bad87847d52c14cf0c4abcf0703b749793f212111ffb303b",
+        "c1579fa02c614f3762b2a799b51e41cebb8f820f34fa736af02c56de2460ce3c",
+        "0ad8d1ea1b8dd788979b4379581218df9321bdce5567eca42ae6be7d395f1a54",
+        "8f2c880890996c587aa500cf8b6da03372de706a9f96075744bb0956ea6fbaac"
+    );
+}
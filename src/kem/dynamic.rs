@@ -0,0 +1,285 @@
+//! Kyber with the parameter set chosen at runtime
+//!
+//! Keys and ciphertexts carry the [`KyberVariant`] they belong to.
+//! Combining inputs of different variants returns
+//! [`Error::VariantMismatch`].
+
+// Keys and ciphertexts are stored inline, as in the parameter-set modules,
+// so that they don't need an allocator.
+#![allow(clippy::large_enum_variant)]
+
+use rand_core::{CryptoRng, RngCore};
+
+use super::{kyber1024, kyber512, kyber768, SharedSecret};
+use crate::Error;
+
+/// The Kyber parameter sets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum KyberVariant {
+    /// Kyber512, see [`kyber512`]
+    Kyber512,
+    /// Kyber768, see [`kyber768`]
+    Kyber768,
+    /// Kyber1024, see [`kyber1024`]
+    Kyber1024,
+}
+
+impl KyberVariant {
+    /// Length of an encoded public key in bytes
+    pub const fn public_key_len(self) -> usize {
+        match self {
+            KyberVariant::Kyber512 => kyber512::PublicKey::LEN,
+            KyberVariant::Kyber768 => kyber768::PublicKey::LEN,
+            KyberVariant::Kyber1024 => kyber1024::PublicKey::LEN,
+        }
+    }
+
+    /// Length of an encoded secret key in bytes
+    pub const fn secret_key_len(self) -> usize {
+        match self {
+            KyberVariant::Kyber512 => kyber512::SecretKey::LEN,
+            KyberVariant::Kyber768 => kyber768::SecretKey::LEN,
+            KyberVariant::Kyber1024 => kyber1024::SecretKey::LEN,
+        }
+    }
+
+    /// Length of a ciphertext in bytes
+    pub const fn ciphertext_len(self) -> usize {
+        match self {
+            KyberVariant::Kyber512 => kyber512::Ciphertext::LEN,
+            KyberVariant::Kyber768 => kyber768::Ciphertext::LEN,
+            KyberVariant::Kyber1024 => kyber1024::Ciphertext::LEN,
+        }
+    }
+}
+
+/// Public key of any Kyber parameter set
+pub enum PublicKey {
+    /// A Kyber512 public key
+    Kyber512(kyber512::PublicKey),
+    /// A Kyber768 public key
+    Kyber768(kyber768::PublicKey),
+    /// A Kyber1024 public key
+    Kyber1024(kyber1024::PublicKey),
+}
+
+impl PublicKey {
+    /// Parse a public key of the given variant
+    pub fn try_from_bytes(variant: KyberVariant, bytes: &[u8]) -> Result<Self, Error> {
+        Ok(match variant {
+            KyberVariant::Kyber512 => PublicKey::Kyber512(bytes.try_into()?),
+            KyberVariant::Kyber768 => PublicKey::Kyber768(bytes.try_into()?),
+            KyberVariant::Kyber1024 => PublicKey::Kyber1024(bytes.try_into()?),
+        })
+    }
+
+    /// The parameter set this key belongs to
+    pub fn variant(&self) -> KyberVariant {
+        match self {
+            PublicKey::Kyber512(_) => KyberVariant::Kyber512,
+            PublicKey::Kyber768(_) => KyberVariant::Kyber768,
+            PublicKey::Kyber1024(_) => KyberVariant::Kyber1024,
+        }
+    }
+
+    /// The encoded public key
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            PublicKey::Kyber512(pk) => pk.as_bytes(),
+            PublicKey::Kyber768(pk) => pk.as_bytes(),
+            PublicKey::Kyber1024(pk) => pk.as_bytes(),
+        }
+    }
+}
+
+/// Secret key of any Kyber parameter set
+pub enum SecretKey {
+    /// A Kyber512 secret key
+    Kyber512(kyber512::SecretKey),
+    /// A Kyber768 secret key
+    Kyber768(kyber768::SecretKey),
+    /// A Kyber1024 secret key
+    Kyber1024(kyber1024::SecretKey),
+}
+
+impl SecretKey {
+    /// Parse a secret key of the given variant
+    pub fn try_from_bytes(variant: KyberVariant, bytes: &[u8]) -> Result<Self, Error> {
+        Ok(match variant {
+            KyberVariant::Kyber512 => SecretKey::Kyber512(bytes.try_into()?),
+            KyberVariant::Kyber768 => SecretKey::Kyber768(bytes.try_into()?),
+            KyberVariant::Kyber1024 => SecretKey::Kyber1024(bytes.try_into()?),
+        })
+    }
+
+    /// The parameter set this key belongs to
+    pub fn variant(&self) -> KyberVariant {
+        match self {
+            SecretKey::Kyber512(_) => KyberVariant::Kyber512,
+            SecretKey::Kyber768(_) => KyberVariant::Kyber768,
+            SecretKey::Kyber1024(_) => KyberVariant::Kyber1024,
+        }
+    }
+
+    /// The encoded secret key
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            SecretKey::Kyber512(sk) => sk.as_bytes(),
+            SecretKey::Kyber768(sk) => sk.as_bytes(),
+            SecretKey::Kyber1024(sk) => sk.as_bytes(),
+        }
+    }
+
+    /// Derive the public key, which is embedded in the secret key
+    pub fn public_key(&self) -> PublicKey {
+        match self {
+            SecretKey::Kyber512(sk) => PublicKey::Kyber512(sk.public_key()),
+            SecretKey::Kyber768(sk) => PublicKey::Kyber768(sk.public_key()),
+            SecretKey::Kyber1024(sk) => PublicKey::Kyber1024(sk.public_key()),
+        }
+    }
+}
+
+/// Ciphertext of any Kyber parameter set
+pub enum Ciphertext {
+    /// A Kyber512 ciphertext
+    Kyber512(kyber512::Ciphertext),
+    /// A Kyber768 ciphertext
+    Kyber768(kyber768::Ciphertext),
+    /// A Kyber1024 ciphertext
+    Kyber1024(kyber1024::Ciphertext),
+}
+
+impl Ciphertext {
+    /// Parse a ciphertext of the given variant
+    pub fn try_from_bytes(variant: KyberVariant, bytes: &[u8]) -> Result<Self, Error> {
+        Ok(match variant {
+            KyberVariant::Kyber512 => Ciphertext::Kyber512(bytes.try_into()?),
+            KyberVariant::Kyber768 => Ciphertext::Kyber768(bytes.try_into()?),
+            KyberVariant::Kyber1024 => Ciphertext::Kyber1024(bytes.try_into()?),
+        })
+    }
+
+    /// The parameter set this ciphertext belongs to
+    pub fn variant(&self) -> KyberVariant {
+        match self {
+            Ciphertext::Kyber512(_) => KyberVariant::Kyber512,
+            Ciphertext::Kyber768(_) => KyberVariant::Kyber768,
+            Ciphertext::Kyber1024(_) => KyberVariant::Kyber1024,
+        }
+    }
+
+    /// The encoded ciphertext
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Ciphertext::Kyber512(ct) => ct.as_bytes(),
+            Ciphertext::Kyber768(ct) => ct.as_bytes(),
+            Ciphertext::Kyber1024(ct) => ct.as_bytes(),
+        }
+    }
+}
+
+/// Generate a new key pair of the given variant
+pub fn keypair(
+    variant: KyberVariant,
+    rng: &mut (impl CryptoRng + RngCore),
+) -> Result<(PublicKey, SecretKey), Error> {
+    Ok(match variant {
+        KyberVariant::Kyber512 => {
+            let (pk, sk) = kyber512::keypair(rng)?;
+            (PublicKey::Kyber512(pk), SecretKey::Kyber512(sk))
+        }
+        KyberVariant::Kyber768 => {
+            let (pk, sk) = kyber768::keypair(rng)?;
+            (PublicKey::Kyber768(pk), SecretKey::Kyber768(sk))
+        }
+        KyberVariant::Kyber1024 => {
+            let (pk, sk) = kyber1024::keypair(rng)?;
+            (PublicKey::Kyber1024(pk), SecretKey::Kyber1024(sk))
+        }
+    })
+}
+
+/// Generate a ciphertext and shared secret for the public key
+pub fn encaps(
+    pk: &PublicKey,
+    rng: &mut (impl CryptoRng + RngCore),
+) -> Result<(Ciphertext, SharedSecret), Error> {
+    Ok(match pk {
+        PublicKey::Kyber512(pk) => {
+            let (ct, ss) = kyber512::encaps(pk, rng)?;
+            (Ciphertext::Kyber512(ct), ss)
+        }
+        PublicKey::Kyber768(pk) => {
+            let (ct, ss) = kyber768::encaps(pk, rng)?;
+            (Ciphertext::Kyber768(ct), ss)
+        }
+        PublicKey::Kyber1024(pk) => {
+            let (ct, ss) = kyber1024::encaps(pk, rng)?;
+            (Ciphertext::Kyber1024(ct), ss)
+        }
+    })
+}
+
+/// Recover the shared secret from a ciphertext
+///
+/// Returns [`Error::VariantMismatch`] if the ciphertext and the secret key
+/// belong to different parameter sets.
+pub fn decaps(ct: &Ciphertext, sk: &SecretKey) -> Result<SharedSecret, Error> {
+    match (ct, sk) {
+        (Ciphertext::Kyber512(ct), SecretKey::Kyber512(sk)) => Ok(kyber512::decaps(ct, sk)),
+        (Ciphertext::Kyber768(ct), SecretKey::Kyber768(sk)) => Ok(kyber768::decaps(ct, sk)),
+        (Ciphertext::Kyber1024(ct), SecretKey::Kyber1024(sk)) => Ok(kyber1024::decaps(ct, sk)),
+        _ => Err(Error::VariantMismatch),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::thread_rng;
+
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        for variant in [
+            KyberVariant::Kyber512,
+            KyberVariant::Kyber768,
+            KyberVariant::Kyber1024,
+        ] {
+            let (pk, sk) = keypair(variant, &mut thread_rng()).unwrap();
+            assert_eq!(pk.variant(), variant);
+            assert_eq!(pk.as_bytes().len(), variant.public_key_len());
+            assert_eq!(sk.as_bytes().len(), variant.secret_key_len());
+
+            let (ct, ss) = encaps(&pk, &mut thread_rng()).unwrap();
+            assert_eq!(ct.variant(), variant);
+            assert_eq!(ct.as_bytes().len(), variant.ciphertext_len());
+            assert_eq!(ss.as_bytes(), decaps(&ct, &sk).unwrap().as_bytes());
+
+            let pk = PublicKey::try_from_bytes(variant, pk.as_bytes()).unwrap();
+            let sk = SecretKey::try_from_bytes(variant, sk.as_bytes()).unwrap();
+            let ct = Ciphertext::try_from_bytes(variant, ct.as_bytes()).unwrap();
+            assert_eq!(ss.as_bytes(), decaps(&ct, &sk).unwrap().as_bytes());
+            assert_eq!(pk.as_bytes(), sk.public_key().as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let (pk, _) = keypair(KyberVariant::Kyber512, &mut thread_rng()).unwrap();
+        let (_, sk) = keypair(KyberVariant::Kyber768, &mut thread_rng()).unwrap();
+        let (ct, _) = encaps(&pk, &mut thread_rng()).unwrap();
+        assert_eq!(decaps(&ct, &sk).err(), Some(Error::VariantMismatch));
+
+        assert_eq!(
+            PublicKey::try_from_bytes(KyberVariant::Kyber768, pk.as_bytes()).err(),
+            Some(Error::InvalidKeyLength)
+        );
+        assert_eq!(
+            Ciphertext::try_from_bytes(KyberVariant::Kyber1024, ct.as_bytes()).err(),
+            Some(Error::InvalidCiphertextLength)
+        );
+    }
+}
@@ -1,21 +1,26 @@
-#![allow(incomplete_features)]
+#![no_std]
+#![forbid(unsafe_code)]
 #![allow(clippy::identity_op)]
-#![feature(array_chunks, adt_const_params, generic_const_exprs)]
-#![feature(
-    maybe_uninit_uninit_array,
-    maybe_uninit_array_assume_init,
-    maybe_uninit_slice,
-    maybe_uninit_write_slice
-)]
+
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
 
 mod utils;
 
+mod error;
+pub use error::Error;
+
 mod indcpa;
 pub mod kem;
 mod params;
+pub use params::{ParameterSet, Params1024, Params512, Params768};
+mod cbd;
+mod ntt;
 mod poly;
 mod polyvec;
-mod symmetric;
-mod cbd;
 mod reduce;
-mod ntt;
\ No newline at end of file
+mod symmetric;
+mod verify;
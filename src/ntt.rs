@@ -32,7 +32,7 @@ pub fn ntt(r: &mut [i16; 256]) {
             for j in start..start + len {
                 let t = fqmul(zeta, r[j + len]);
                 r[j + len] = r[j] - t;
-                r[j] = r[j] + t;
+                r[j] += t;
             }
             start += 2 * len;
         }
@@ -54,7 +54,7 @@ pub fn invntt(r: &mut [i16; 256]) {
             for j in start..start + len {
                 let t = r[j];
                 r[j] = barrett_reduce(t + r[j + len]);
-                r[j + len] = r[j + len] - t;
+                r[j + len] -= t;
                 r[j + len] = fqmul(zeta, r[j + len]);
             }
             start += 2 * len;
@@ -75,4 +75,4 @@ pub fn basemul(a: [i16; 2], b: [i16; 2], zeta: i16) -> [i16; 2] {
     r[1] += fqmul(a[1], b[0]);
 
     r
-}
\ No newline at end of file
+}
@@ -1,6 +1,16 @@
 //! Constants and functions to compute derived constants
 #![allow(unused)]
 
+use core::fmt::Debug;
+
+use hybrid_array::{
+    sizes::{
+        U1088, U1152, U1184, U128, U1408, U1536, U1568, U160, U1632, U192, U2, U2400, U3, U3168,
+        U4, U640, U768, U800, U960,
+    },
+    ArraySize,
+};
+
 /// N parameter in Kyber
 pub const KYBER_N: usize = 256;
 
@@ -16,45 +26,192 @@ pub const KYBER_SSBYTES: usize = 32;
 /// Size of polynomial
 pub const KYBER_POLYBYTES: usize = 384;
 
-/// Size of a polynomial vector
-pub const fn kyber_polyvec_compressed_bytes<const K: usize>() -> usize {
-    if K == 2 || K == 3 {
-        320 * K
-    } else if K == 4 {
-        352 * K
-    } else {
-        0
-    }
-}
+/// A Kyber parameter set
+///
+/// This trait is sealed: it is implemented for [`Params512`], [`Params768`]
+/// and [`Params1024`] only, so an unsupported rank can't be instantiated.
+/// All sizes are in bytes.
+///
+/// The sizes of the arrays a parameter set works with are also given as
+/// type-level integers, so that they can be used as array lengths in code
+/// that is generic over the parameter set.
+pub trait ParameterSet:
+    sealed::Sealed + Copy + Clone + Debug + Default + PartialEq + Eq + Send + Sync + 'static
+{
+    /// Rank of the module, i.e. the number of polynomials in a vector
+    const K: usize;
+    /// Parameter of the noise distribution of the secret and in encryption
+    const ETA1: usize;
+    /// Parameter of the noise distribution of the errors in encryption
+    const ETA2: usize = 2;
+    /// Bits per coefficient of the compressed vector `u` of a ciphertext
+    const DU: usize;
+    /// Bits per coefficient of the compressed polynomial `v` of a ciphertext
+    const DV: usize;
 
-/// Size of compressed polynomial
-pub const fn kyber_poly_compressed_bytes<const K: usize>() -> usize {
-    if K == 2 || K == 3 {
-        128
-    } else {
-        160
-    }
+    /// PRF output needed to sample one polynomial with `ETA1`
+    const ETA1_RANDOMNESS_BYTES: usize = Self::ETA1 * KYBER_N / 4;
+    /// PRF output needed to sample one polynomial with `ETA2`
+    const ETA2_RANDOMNESS_BYTES: usize = Self::ETA2 * KYBER_N / 4;
+
+    /// Size of a serialized polynomial vector
+    const POLYVEC_BYTES: usize = Self::K * KYBER_POLYBYTES;
+    /// Size of a compressed polynomial
+    const POLY_COMPRESSED_BYTES: usize = Self::DV * KYBER_N / 8;
+    /// Size of a compressed polynomial vector
+    const POLYVEC_COMPRESSED_BYTES: usize = Self::K * Self::DU * KYBER_N / 8;
+
+    /// Size of the public key of the CPA-secure scheme
+    const INDCPA_PK_BYTES: usize = Self::POLYVEC_BYTES + KYBER_SYMBYTES;
+    /// Size of the secret key of the CPA-secure scheme
+    const INDCPA_SK_BYTES: usize = Self::POLYVEC_BYTES;
+    /// Size of a ciphertext of the CPA-secure scheme
+    const INDCPA_BYTES: usize = Self::POLYVEC_COMPRESSED_BYTES + Self::POLY_COMPRESSED_BYTES;
+
+    /// Size of the public key of the KEM
+    const PK_BYTES: usize = Self::INDCPA_PK_BYTES;
+    /// Size of the secret key of the KEM
+    ///
+    /// The secret key also holds the public key, its hash and the value
+    /// returned on implicit rejection.
+    const SK_BYTES: usize = Self::INDCPA_SK_BYTES + Self::INDCPA_PK_BYTES + 2 * KYBER_SYMBYTES;
+    /// Size of a ciphertext of the KEM
+    const CT_BYTES: usize = Self::INDCPA_BYTES;
+
+    /// `K` as a type
+    type Rank: ArraySize;
+    /// `ETA1_RANDOMNESS_BYTES` as a type
+    type Eta1RandomnessBytes: ArraySize;
+    /// `ETA2_RANDOMNESS_BYTES` as a type
+    type Eta2RandomnessBytes: ArraySize;
+    /// `POLYVEC_BYTES` (and `INDCPA_SK_BYTES`) as a type
+    type PolyVecBytes: ArraySize;
+    /// `POLY_COMPRESSED_BYTES` as a type
+    type PolyCompressedBytes: ArraySize;
+    /// `POLYVEC_COMPRESSED_BYTES` as a type
+    type PolyVecCompressedBytes: ArraySize;
+    /// `INDCPA_PK_BYTES` (and `PK_BYTES`) as a type
+    type IndcpaPkBytes: ArraySize;
+    /// `INDCPA_BYTES` (and `CT_BYTES`) as a type
+    type IndcpaBytes: ArraySize;
+    /// `SK_BYTES` as a type
+    type SkBytes: ArraySize;
 }
 
-pub const fn kyber_polyvec_bytes<const K: usize>() -> usize {
-    K * KYBER_POLYBYTES
+mod sealed {
+    pub trait Sealed {}
 }
 
-/// Value of eta1 for this Kyber instance
-pub const fn kyber_eta1<const K: usize>() -> usize {
-    if K == 2 {
-        3
-    } else {
-        2
-    }
+/// Parameters of Kyber512 and ML-KEM-512
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Params512;
+
+/// Parameters of Kyber768 and ML-KEM-768
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Params768;
+
+/// Parameters of Kyber1024 and ML-KEM-1024
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Params1024;
+
+impl sealed::Sealed for Params512 {}
+impl sealed::Sealed for Params768 {}
+impl sealed::Sealed for Params1024 {}
+
+impl ParameterSet for Params512 {
+    const K: usize = 2;
+    const ETA1: usize = 3;
+    const DU: usize = 10;
+    const DV: usize = 4;
+
+    type Rank = U2;
+    type Eta1RandomnessBytes = U192;
+    type Eta2RandomnessBytes = U128;
+    type PolyVecBytes = U768;
+    type PolyCompressedBytes = U128;
+    type PolyVecCompressedBytes = U640;
+    type IndcpaPkBytes = U800;
+    type IndcpaBytes = U768;
+    type SkBytes = U1632;
 }
 
-pub const fn kyber_indcpa_pkbytes<const K: usize>() -> usize {
-    kyber_polyvec_bytes::<K>() + KYBER_SYMBYTES
+impl ParameterSet for Params768 {
+    const K: usize = 3;
+    const ETA1: usize = 2;
+    const DU: usize = 10;
+    const DV: usize = 4;
+
+    type Rank = U3;
+    type Eta1RandomnessBytes = U128;
+    type Eta2RandomnessBytes = U128;
+    type PolyVecBytes = U1152;
+    type PolyCompressedBytes = U128;
+    type PolyVecCompressedBytes = U960;
+    type IndcpaPkBytes = U1184;
+    type IndcpaBytes = U1088;
+    type SkBytes = U2400;
 }
 
-pub const fn kyber_indcpa_skbytes<const K: usize>() -> usize {
-    kyber_polyvec_bytes::<K>()
+impl ParameterSet for Params1024 {
+    const K: usize = 4;
+    const ETA1: usize = 2;
+    const DU: usize = 11;
+    const DV: usize = 5;
+
+    type Rank = U4;
+    type Eta1RandomnessBytes = U128;
+    type Eta2RandomnessBytes = U128;
+    type PolyVecBytes = U1536;
+    type PolyCompressedBytes = U160;
+    type PolyVecCompressedBytes = U1408;
+    type IndcpaPkBytes = U1568;
+    type IndcpaBytes = U1568;
+    type SkBytes = U3168;
 }
 
-pub const KYBER_ETA2: usize = 2;
+/// Size in bytes of the messages encrypted by the CPA-secure scheme
+pub const KYBER_INDCPA_MSGBYTES: usize = KYBER_SYMBYTES;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hybrid_array::typenum::Unsigned;
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(Params512::PK_BYTES, 800);
+        assert_eq!(Params512::SK_BYTES, 1632);
+        assert_eq!(Params512::CT_BYTES, 768);
+
+        assert_eq!(Params768::PK_BYTES, 1184);
+        assert_eq!(Params768::SK_BYTES, 2400);
+        assert_eq!(Params768::CT_BYTES, 1088);
+
+        assert_eq!(Params1024::PK_BYTES, 1568);
+        assert_eq!(Params1024::SK_BYTES, 3168);
+        assert_eq!(Params1024::CT_BYTES, 1568);
+    }
+
+    fn check_array_sizes<P: ParameterSet>() {
+        assert_eq!(P::Rank::USIZE, P::K);
+        assert_eq!(P::Eta1RandomnessBytes::USIZE, P::ETA1_RANDOMNESS_BYTES);
+        assert_eq!(P::Eta2RandomnessBytes::USIZE, P::ETA2_RANDOMNESS_BYTES);
+        assert_eq!(P::PolyVecBytes::USIZE, P::POLYVEC_BYTES);
+        assert_eq!(P::PolyVecBytes::USIZE, P::INDCPA_SK_BYTES);
+        assert_eq!(P::PolyCompressedBytes::USIZE, P::POLY_COMPRESSED_BYTES);
+        assert_eq!(
+            P::PolyVecCompressedBytes::USIZE,
+            P::POLYVEC_COMPRESSED_BYTES
+        );
+        assert_eq!(P::IndcpaPkBytes::USIZE, P::INDCPA_PK_BYTES);
+        assert_eq!(P::IndcpaBytes::USIZE, P::INDCPA_BYTES);
+        assert_eq!(P::SkBytes::USIZE, P::SK_BYTES);
+    }
+
+    #[test]
+    fn test_array_sizes() {
+        check_array_sizes::<Params512>();
+        check_array_sizes::<Params768>();
+        check_array_sizes::<Params1024>();
+    }
+}
@@ -1,35 +1,78 @@
 //! Polynomial arithmetic
 
-use std::{
-    intrinsics::transmute,
-    mem::MaybeUninit,
+use core::{
+    marker::PhantomData,
     ops::{Add, AddAssign, Sub, SubAssign},
 };
 
+use hybrid_array::Array;
+
 #[cfg(test)]
 use rand::prelude::*;
 
-use crate::{cbd::poly_cbd_eta1, ntt::{invntt, ntt}, params::*, reduce::barrett_reduce, symmetric::prf};
+use crate::{
+    cbd::{poly_cbd_eta1, poly_cbd_eta2},
+    ntt::{basemul, invntt, ntt, ZETAS},
+    params::*,
+    reduce::{barrett_reduce, montgomery_reduce},
+    symmetric::Symmetric,
+    utils::wipe,
+};
 
 /// Polynomial
 #[derive(Debug, PartialEq, Clone)]
-pub struct Poly<const K: usize> {
+pub struct Poly<P: ParameterSet> {
     pub(crate) coeffs: [i16; KYBER_N],
+    params: PhantomData<P>,
 }
 
-fn positive_repr(p: i16) -> u16 {
+#[cfg(feature = "zeroize")]
+impl<P: ParameterSet> zeroize::Zeroize for Poly<P> {
+    fn zeroize(&mut self) {
+        self.coeffs.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<P: ParameterSet> Drop for Poly<P> {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(self);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<P: ParameterSet> zeroize::ZeroizeOnDrop for Poly<P> {}
+
+pub(crate) fn positive_repr(p: i16) -> u16 {
     let result = p + ((p >> 15) & KYBER_Q as i16);
     debug_assert!(result >= 0 && result < KYBER_Q as i16);
     result as u16
 }
 
-impl<const K: usize> Poly<K> {
-    pub const COMPRESSED_BYTES: usize = kyber_poly_compressed_bytes::<K>();
+/// Compute `round(2^d * x / q) mod 2^d` for `0 <= x < q`
+///
+/// This multiplies by an approximate reciprocal of q instead of dividing,
+/// since division takes data-dependent time on many targets (KyberSlash).
+/// The constants are those of the reference implementation.
+#[inline(always)]
+pub(crate) fn compress_coeff(x: u16, d: u32) -> u16 {
+    let x = x as u64;
+    let t = match d {
+        1 | 4 => (((x << d) + 1665) * 80635) >> 28,
+        5 => (((x << 5) + 1664) * 40318) >> 27,
+        10 => (((x << 10) + 1665) * 1290167) >> 32,
+        11 => (((x << 11) + 1664) * 645084) >> 31,
+        _ => unreachable!(),
+    };
+    (t & ((1 << d) - 1)) as u16
+}
 
+impl<P: ParameterSet> Poly<P> {
     /// Create a new empty polynomial
     pub(crate) fn zero() -> Self {
         Poly {
             coeffs: [0; KYBER_N],
+            params: PhantomData,
         }
     }
 
@@ -68,19 +111,17 @@ impl<const K: usize> Poly<K> {
     }
 
     /// Compression and serialization of a polynomial
-    pub fn compress_into(&self, out: &mut [u8; Self::COMPRESSED_BYTES]) {
+    pub fn compress_into(&self, out: &mut Array<u8, P::PolyCompressedBytes>) {
         let mut tmp = [0u8; 8];
-        debug_assert!(Self::COMPRESSED_BYTES == 128 || Self::COMPRESSED_BYTES == 160);
+        debug_assert!(P::DV == 4 || P::DV == 5);
 
-        if Self::COMPRESSED_BYTES == 128 {
+        if P::DV == 4 {
             for i in 0..(KYBER_N / 8) {
                 #[allow(clippy::needless_range_loop)]
                 for j in 0..8 {
                     // map to positive standard representation
-                    const Q: u32 = KYBER_Q as u32;
-                    let u = positive_repr(self.coeffs[8 * i + j]) as u32;
-                    let u = (((u << 4) + Q / 2) / Q) & 15;
-                    tmp[j] = u as u8;
+                    let u = positive_repr(self.coeffs[8 * i + j]);
+                    tmp[j] = compress_coeff(u, 4) as u8;
                 }
 
                 out[i * 4 + 0] = tmp[0] | (tmp[1] << 4);
@@ -93,10 +134,8 @@ impl<const K: usize> Poly<K> {
                 #[allow(clippy::needless_range_loop)]
                 for j in 0..8 {
                     // map to positive standard representation
-                    const Q: u32 = KYBER_Q as u32;
-                    let u = positive_repr(self.coeffs[8 * i + j]) as u32;
-                    let u = (((u << 5) + Q / 2) / Q) & 31;
-                    tmp[j] = u as u8;
+                    let u = positive_repr(self.coeffs[8 * i + j]);
+                    tmp[j] = compress_coeff(u, 5) as u8;
                 }
 
                 out[i * 5 + 0] = (tmp[0] >> 0) | (tmp[1] << 5);
@@ -111,12 +150,12 @@ impl<const K: usize> Poly<K> {
     /// De-serialize and decompress a polynomial
     ///
     /// **Approximate** inverse of `compress_into`
-    pub fn decompress(buf: &[u8; Self::COMPRESSED_BYTES]) -> Self {
-        debug_assert!(Self::COMPRESSED_BYTES == 128 || Self::COMPRESSED_BYTES == 160);
+    pub fn decompress(buf: &Array<u8, P::PolyCompressedBytes>) -> Self {
+        debug_assert!(P::DV == 4 || P::DV == 5);
 
         let mut out = Self::zero();
 
-        if Self::COMPRESSED_BYTES == 128 {
+        if P::DV == 4 {
             #[allow(clippy::needless_range_loop)]
             for i in 0..KYBER_N / 2 {
                 let a = (((buf[i] & 15) as u32 * KYBER_Q as u32 + 8) >> 4) as i16;
@@ -148,13 +187,33 @@ impl<const K: usize> Poly<K> {
         out
     }
 
-    pub fn from_noise_eta1(seed: &[u8; KYBER_SYMBYTES], nonce: u8) -> Self
-    where
-        [(); kyber_eta1::<K>() * KYBER_N / 4]: ,
-    {
-        let mut buf = [0u8; kyber_eta1::<K>() * KYBER_N / 4];
-        prf(seed, nonce, &mut buf);
-        poly_cbd_eta1(&buf)
+    pub(crate) fn from_noise_eta1<S: Symmetric>(seed: &[u8; KYBER_SYMBYTES], nonce: u8) -> Self {
+        let mut buf = Array::<u8, P::Eta1RandomnessBytes>::default();
+        S::prf(seed, nonce, &mut buf);
+        let poly = poly_cbd_eta1(&buf);
+        wipe(&mut buf);
+        poly
+    }
+
+    /// Sample noise polynomials for four seeds and the same nonce, see
+    /// [`Poly::from_noise_eta1`]
+    pub(crate) fn from_noise_eta1_x4<S: Symmetric>(
+        seeds: [&[u8; KYBER_SYMBYTES]; 4],
+        nonce: u8,
+    ) -> [Self; 4] {
+        let mut bufs: [Array<u8, P::Eta1RandomnessBytes>; 4] = Default::default();
+        S::prf_x4(seeds, nonce, bufs.each_mut().map(|buf| &mut buf[..]));
+        let polys = bufs.each_ref().map(poly_cbd_eta1);
+        bufs.iter_mut().for_each(|buf| wipe(buf));
+        polys
+    }
+
+    pub(crate) fn from_noise_eta2<S: Symmetric>(seed: &[u8; KYBER_SYMBYTES], nonce: u8) -> Self {
+        let mut buf = Array::<u8, P::Eta2RandomnessBytes>::default();
+        S::prf(seed, nonce, &mut buf);
+        let poly = poly_cbd_eta2(&buf);
+        wipe(&mut buf);
+        poly
     }
 
     /// Perform in-place NTT on this polynomial
@@ -164,16 +223,68 @@ impl<const K: usize> Poly<K> {
     }
 
     /// Barrett-reduce all coefficients of this polynomial
-    fn reduce(&mut self) {
+    pub(crate) fn reduce(&mut self) {
         self.coeffs
             .iter_mut()
             .for_each(|el| *el = barrett_reduce(*el));
     }
 
     /// Perform the inverse NTT
+    ///
+    /// This also multiplies by the Montgomery factor 2^16.
     pub fn invntt(&mut self) {
         invntt(&mut self.coeffs);
     }
+
+    /// Convert all coefficients from normal domain to Montgomery domain
+    pub fn tomont(&mut self) {
+        const F: i32 = ((1u64 << 32) % KYBER_Q as u64) as i32;
+        self.coeffs
+            .iter_mut()
+            .for_each(|el| *el = montgomery_reduce(*el as i32 * F));
+    }
+
+    /// Multiply two polynomials in the NTT domain
+    ///
+    /// The result is multiplied by the inverse Montgomery factor 2^-16.
+    pub fn basemul_montgomery(&self, other: &Self) -> Self {
+        let mut out = Self::zero();
+        for i in 0..KYBER_N / 4 {
+            let zeta = ZETAS[64 + i];
+            for (offset, zeta) in [(4 * i, zeta), (4 * i + 2, -zeta)] {
+                let r = basemul(
+                    [self.coeffs[offset], self.coeffs[offset + 1]],
+                    [other.coeffs[offset], other.coeffs[offset + 1]],
+                    zeta,
+                );
+                out.coeffs[offset..offset + 2].copy_from_slice(&r);
+            }
+        }
+        out
+    }
+
+    /// Convert a 32-byte message to a polynomial
+    pub fn from_msg(msg: &[u8; KYBER_INDCPA_MSGBYTES]) -> Self {
+        let mut poly = Self::zero();
+        for (i, byte) in msg.iter().enumerate() {
+            for j in 0..8 {
+                let mask = -(((byte >> j) & 1) as i16);
+                poly.coeffs[8 * i + j] = mask & ((KYBER_Q as i16 + 1) / 2);
+            }
+        }
+        poly
+    }
+
+    /// Convert a polynomial to a 32-byte message
+    pub fn to_msg(&self, msg: &mut [u8; KYBER_INDCPA_MSGBYTES]) {
+        for (i, byte) in msg.iter_mut().enumerate() {
+            *byte = 0;
+            for j in 0..8 {
+                let t = compress_coeff(positive_repr(self.coeffs[8 * i + j]), 1);
+                *byte |= (t << j) as u8;
+            }
+        }
+    }
 }
 
 // All the arithmetic implementations follow.
@@ -183,67 +294,56 @@ impl<const K: usize> Poly<K> {
 
 macro_rules! poly_binary_op {
     ($self: ident, $rhs: ident, $operation: tt) => {{
-        // This follows the exmaple from MaybeUninit
-        // This is safe because initializing MaybeUninits isn't necessary
-        let mut coeffs: [MaybeUninit<i16>; KYBER_N] =
-            unsafe { MaybeUninit::uninit().assume_init() };
-        // this will generate all the new result items
-        let resultiter = $self
-            .coeffs
-            .iter()
-            .zip($rhs.coeffs.iter())
-            .map(|(l, r)| l $operation r);
-        // The maybeuninits are safe to drop, because dropping a maybeuninit is a no-op.
-        coeffs
-            .iter_mut()
-            .zip(resultiter)
-            .for_each(|(dest, src)| *dest = MaybeUninit::new(src));
-        // Everything is initialized now, so we can transmute into the final item.
-        let coeffs: [i16; KYBER_N] = unsafe { transmute(coeffs) };
+        // the result is written directly, without zero-initializing it first
+        let coeffs: [i16; KYBER_N] =
+            core::array::from_fn(|i| $self.coeffs[i] $operation $rhs.coeffs[i]);
 
-        Poly { coeffs }
+        Poly {
+            coeffs,
+            params: PhantomData,
+        }
     }}
 }
 
-impl<const K: usize> Add for &Poly<K> {
-    type Output = Poly<K>;
+impl<P: ParameterSet> Add for &Poly<P> {
+    type Output = Poly<P>;
 
     fn add(self, rhs: Self) -> Self::Output {
         poly_binary_op!(self, rhs, +)
     }
 }
 
-impl<const K: usize> Add<&Poly<K>> for Poly<K> {
-    type Output = Poly<K>;
+impl<P: ParameterSet> Add<&Poly<P>> for Poly<P> {
+    type Output = Poly<P>;
 
-    fn add(mut self, rhs: &Poly<K>) -> Self::Output {
+    fn add(mut self, rhs: &Poly<P>) -> Self::Output {
         self += rhs;
         self
     }
 }
 
-impl<const K: usize> Add<Poly<K>> for &Poly<K> {
-    type Output = Poly<K>;
+impl<P: ParameterSet> Add<Poly<P>> for &Poly<P> {
+    type Output = Poly<P>;
 
-    fn add(self, mut rhs: Poly<K>) -> Self::Output {
+    fn add(self, mut rhs: Poly<P>) -> Self::Output {
         rhs.coeffs
             .iter_mut()
             .zip(self.coeffs.iter().copied())
-            .for_each(|(r, l)| *r = l + *r);
+            .for_each(|(r, l)| *r += l);
         rhs
     }
 }
 
-impl<const K: usize> Add for Poly<K> {
-    type Output = Poly<K>;
+impl<P: ParameterSet> Add for Poly<P> {
+    type Output = Poly<P>;
 
-    fn add(mut self, rhs: Poly<K>) -> Self::Output {
+    fn add(mut self, rhs: Poly<P>) -> Self::Output {
         self += rhs;
         self
     }
 }
 
-impl<const K: usize> AddAssign<&Poly<K>> for Poly<K> {
+impl<P: ParameterSet> AddAssign<&Poly<P>> for Poly<P> {
     fn add_assign(&mut self, rhs: &Self) {
         self.coeffs
             .iter_mut()
@@ -252,22 +352,22 @@ impl<const K: usize> AddAssign<&Poly<K>> for Poly<K> {
     }
 }
 
-impl<const K: usize> AddAssign for Poly<K> {
+impl<P: ParameterSet> AddAssign for Poly<P> {
     fn add_assign(&mut self, rhs: Self) {
         *self += &rhs;
     }
 }
 
-impl<const K: usize> Sub for &Poly<K> {
-    type Output = Poly<K>;
+impl<P: ParameterSet> Sub for &Poly<P> {
+    type Output = Poly<P>;
 
     fn sub(self, rhs: Self) -> Self::Output {
         poly_binary_op!(self, rhs, -)
     }
 }
 
-impl<const K: usize> Sub for Poly<K> {
-    type Output = Poly<K>;
+impl<P: ParameterSet> Sub for Poly<P> {
+    type Output = Poly<P>;
 
     fn sub(mut self, rhs: Self) -> Self::Output {
         self -= rhs;
@@ -275,19 +375,19 @@ impl<const K: usize> Sub for Poly<K> {
     }
 }
 
-impl<const K: usize> Sub<&Poly<K>> for Poly<K> {
-    type Output = Poly<K>;
+impl<P: ParameterSet> Sub<&Poly<P>> for Poly<P> {
+    type Output = Poly<P>;
 
-    fn sub(mut self, rhs: &Poly<K>) -> Self::Output {
+    fn sub(mut self, rhs: &Poly<P>) -> Self::Output {
         self -= rhs;
         self
     }
 }
 
-impl<const K: usize> Sub<Poly<K>> for &Poly<K> {
-    type Output = Poly<K>;
+impl<P: ParameterSet> Sub<Poly<P>> for &Poly<P> {
+    type Output = Poly<P>;
 
-    fn sub(self, mut rhs: Poly<K>) -> Self::Output {
+    fn sub(self, mut rhs: Poly<P>) -> Self::Output {
         rhs.coeffs
             .iter_mut()
             .zip(self.coeffs.iter().copied())
@@ -296,7 +396,7 @@ impl<const K: usize> Sub<Poly<K>> for &Poly<K> {
     }
 }
 
-impl<const K: usize> SubAssign<&Poly<K>> for Poly<K> {
+impl<P: ParameterSet> SubAssign<&Poly<P>> for Poly<P> {
     fn sub_assign(&mut self, rhs: &Self) {
         self.coeffs
             .iter_mut()
@@ -305,7 +405,7 @@ impl<const K: usize> SubAssign<&Poly<K>> for Poly<K> {
     }
 }
 
-impl<const K: usize> SubAssign<Poly<K>> for Poly<K> {
+impl<P: ParameterSet> SubAssign<Poly<P>> for Poly<P> {
     fn sub_assign(&mut self, rhs: Self) {
         *self -= &rhs;
     }
@@ -326,25 +426,43 @@ mod test {
         }
     }
 
+    /// The division-free compression rounds like the textbook formula
+    #[test]
+    fn test_compress_coeff() {
+        const Q: u32 = KYBER_Q as u32;
+        for d in [1, 4, 5, 10, 11] {
+            for x in 0..KYBER_Q as u16 {
+                let expected = ((((x as u32) << d) + Q / 2) / Q) & ((1 << d) - 1);
+                assert_eq!(
+                    compress_coeff(x, d) as u32,
+                    expected,
+                    "x = {}, d = {}",
+                    x,
+                    d
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_new() {
-        let _ = Poly::<2>::zero();
-        let _ = Poly::<3>::zero();
-        let _ = Poly::<4>::zero();
+        let _ = Poly::<Params512>::zero();
+        let _ = Poly::<Params768>::zero();
+        let _ = Poly::<Params1024>::zero();
     }
 
     #[test]
     fn test_poly_compress_calls() {
-        let poly = Poly::<2>::zero();
-        let mut outbuf = [0u8; Poly::<2>::COMPRESSED_BYTES];
+        let poly = Poly::<Params512>::zero();
+        let mut outbuf = Array::default();
         poly.compress_into(&mut outbuf);
 
-        let poly = Poly::<3>::zero();
-        let mut outbuf = [0u8; Poly::<3>::COMPRESSED_BYTES];
+        let poly = Poly::<Params768>::zero();
+        let mut outbuf = Array::default();
         poly.compress_into(&mut outbuf);
 
-        let poly = Poly::<4>::zero();
-        let mut outbuf = [0u8; Poly::<4>::COMPRESSED_BYTES];
+        let poly = Poly::<Params1024>::zero();
+        let mut outbuf = Array::default();
         poly.compress_into(&mut outbuf);
     }
 
@@ -353,11 +471,10 @@ mod test {
     /// Based on https://github.com/cloudflare/circl/blob/62142fc919e58fc8d1d745cfd67f23c62020d6ee/pke/kyber/internal/common/poly_test.go#L44-L69
     #[test]
     fn test_compress_decompress_2() {
-        let poly = Poly::<2>::random();
-        let mut outbuf = [0u8; Poly::<2>::COMPRESSED_BYTES];
+        let poly = Poly::<Params512>::random();
+        let mut outbuf = Array::default();
         poly.compress_into(&mut outbuf);
-        println!("{:x?}", outbuf);
-        let poly2 = Poly::<2>::decompress(&outbuf);
+        let poly2 = Poly::<Params512>::decompress(&outbuf);
 
         for (l, r) in poly
             .coeffs
@@ -365,9 +482,9 @@ mod test {
             .copied()
             .zip(poly2.coeffs.iter().copied())
         {
-            const BOUND: u16 = (KYBER_Q as u16 + 1 << 4) >> 5;
+            const BOUND: u16 = ((KYBER_Q as u16 + 1) << 4) >> 5;
 
-            let diff = s_mod_q(l - r).abs() as u16;
+            let diff = s_mod_q(l - r).unsigned_abs();
             assert!(
                 diff < BOUND,
                 "|{} - {} mod^± q| = {} > {}",
@@ -384,11 +501,10 @@ mod test {
     /// Based on https://github.com/cloudflare/circl/blob/62142fc919e58fc8d1d745cfd67f23c62020d6ee/pke/kyber/internal/common/poly_test.go#L44-L69
     #[test]
     fn test_compress_decompress_4() {
-        let poly = Poly::<4>::random();
-        let mut outbuf = [0u8; Poly::<4>::COMPRESSED_BYTES];
+        let poly = Poly::<Params1024>::random();
+        let mut outbuf = Array::default();
         poly.compress_into(&mut outbuf);
-        println!("{:x?}", outbuf);
-        let poly2 = Poly::<4>::decompress(&outbuf);
+        let poly2 = Poly::<Params1024>::decompress(&outbuf);
 
         for (l, r) in poly
             .coeffs
@@ -396,9 +512,9 @@ mod test {
             .copied()
             .zip(poly2.coeffs.iter().copied())
         {
-            const BOUND: u16 = (KYBER_Q as u16 + 1 << 5) >> 6;
+            const BOUND: u16 = ((KYBER_Q as u16 + 1) << 5) >> 6;
 
-            let diff = s_mod_q(l - r).abs() as u16;
+            let diff = s_mod_q(l - r).unsigned_abs();
             assert!(
                 diff < BOUND,
                 "|{} - {} mod^± q| = {} > {}",
@@ -413,8 +529,8 @@ mod test {
     /// Some arithmetic tests
     #[test]
     fn test_arithmetic() {
-        let zeros = Poly::<2>::zero();
-        let random = Poly::<2>::zero();
+        let zeros = Poly::<Params512>::zero();
+        let random = Poly::<Params512>::zero();
 
         assert_eq!(random, &random + &zeros);
         assert_eq!(random, &random - &zeros);
@@ -427,19 +543,32 @@ mod test {
     /// Test serialization and deserialization of polynomials.
     #[test]
     fn test_serialization() {
-        let start = Poly::<2>::random();
+        let start = Poly::<Params512>::random();
         let mut out = [0u8; KYBER_POLYBYTES];
         start.to_bytes(&mut out);
-        let poly2 = Poly::<2>::from_bytes(&out);
+        let poly2 = Poly::<Params512>::from_bytes(&out);
         assert_eq!(start, poly2);
     }
 
     #[test]
     fn test_ntt_invntt() {
-        let mut p = Poly::<3>::random();
-        let p2 = p.clone();
+        let mut p = Poly::<Params768>::random();
+        let mut p2 = p.clone();
         p.ntt();
         p.invntt();
+        // the inverse NTT leaves the result in the Montgomery domain
+        p2.tomont();
+        p.reduce();
+        p2.reduce();
         assert_eq!(p, p2);
     }
+
+    #[test]
+    fn test_msg_roundtrip() {
+        let msg: [u8; KYBER_INDCPA_MSGBYTES] = crate::utils::random_array();
+        let poly = Poly::<Params512>::from_msg(&msg);
+        let mut out = [0u8; KYBER_INDCPA_MSGBYTES];
+        poly.to_msg(&mut out);
+        assert_eq!(msg, out);
+    }
 }
@@ -1,54 +1,142 @@
-use std::mem::MaybeUninit;
+//! Polynomial vectors
+
+use core::ops::AddAssign;
+
+use hybrid_array::Array;
 
-///! Polynomial vectors
 use crate::{
     params::*,
-    poly::Poly,
+    poly::{compress_coeff, positive_repr, Poly},
 };
 
 #[derive(Debug, PartialEq)]
-pub struct PolyVec<const K: usize> {
-    pub(crate) vec: [Poly<K>; K],
+pub struct PolyVec<P: ParameterSet> {
+    pub(crate) vec: Array<Poly<P>, P::Rank>,
+}
+
+// The polynomials wipe themselves when dropped
+#[cfg(feature = "zeroize")]
+impl<P: ParameterSet> zeroize::Zeroize for PolyVec<P> {
+    fn zeroize(&mut self) {
+        self.vec.iter_mut().for_each(zeroize::Zeroize::zeroize);
+    }
 }
 
-impl<const K: usize> PolyVec<K> {
-    pub(crate) const POLYVECBYTES: usize = K * KYBER_POLYBYTES;
+#[cfg(feature = "zeroize")]
+impl<P: ParameterSet> zeroize::ZeroizeOnDrop for PolyVec<P> {}
 
+impl<P: ParameterSet> PolyVec<P> {
     pub fn new() -> Self {
-        let mut vec = MaybeUninit::uninit_array();
-        for v in &mut vec {
-            *v = MaybeUninit::new(Poly::<K>::zero());
+        PolyVec {
+            vec: Array::from_fn(|_| Poly::<P>::zero()),
         }
-        let vec = unsafe { MaybeUninit::array_assume_init(vec) };
-        PolyVec { vec }
     }
 
     #[cfg(test)]
     pub(crate) fn random() -> Self {
-        let mut vec = MaybeUninit::uninit_array();
-        for poly in vec.iter_mut() {
-            *poly = MaybeUninit::new(Poly::<K>::random());
+        PolyVec {
+            vec: Array::from_fn(|_| Poly::<P>::random()),
         }
-
-        let vec = unsafe { MaybeUninit::array_assume_init(vec) };
-        PolyVec { vec }
     }
 
-    pub fn to_bytes(&self, out: &mut [u8; kyber_polyvec_bytes::<K>()]) {
+    pub fn to_bytes(&self, out: &mut Array<u8, P::PolyVecBytes>) {
         self.vec
             .iter()
-            .zip(out.array_chunks_mut::<KYBER_POLYBYTES>())
-            .for_each(|(vec, outbuf)| vec.to_bytes(outbuf));
+            .zip(out.chunks_exact_mut(KYBER_POLYBYTES))
+            .for_each(|(vec, outbuf)| vec.to_bytes(outbuf.try_into().unwrap()));
+    }
+
+    pub fn from_bytes(input: &Array<u8, P::PolyVecBytes>) -> Self {
+        let mut chunks = input.chunks_exact(KYBER_POLYBYTES);
+        PolyVec {
+            vec: Array::from_fn(|_| Poly::from_bytes(chunks.next().unwrap().try_into().unwrap())),
+        }
     }
 
-    pub fn from_bytes(input: &[u8; kyber_polyvec_bytes::<K>()]) -> Self {
-        let mut vec = MaybeUninit::uninit_array();
-        for (poly, bytes) in vec.iter_mut().zip(input.array_chunks::<KYBER_POLYBYTES>()) {
-            *poly = MaybeUninit::new(Poly::from_bytes(bytes));
+    /// Compress and serialize a vector of polynomials
+    pub fn compress_into(&self, out: &mut Array<u8, P::PolyVecCompressedBytes>) {
+        if P::DU == 11 {
+            let mut t = [0u16; 8];
+            for (poly, out) in self.vec.iter().zip(out.chunks_exact_mut(352)) {
+                for (coeffs, r) in poly.coeffs.chunks_exact(8).zip(out.chunks_exact_mut(11)) {
+                    for (t, &c) in t.iter_mut().zip(coeffs) {
+                        *t = compress_coeff(positive_repr(c), 11);
+                    }
+
+                    r[0] = (t[0] >> 0) as u8;
+                    r[1] = ((t[0] >> 8) | (t[1] << 3)) as u8;
+                    r[2] = ((t[1] >> 5) | (t[2] << 6)) as u8;
+                    r[3] = (t[2] >> 2) as u8;
+                    r[4] = ((t[2] >> 10) | (t[3] << 1)) as u8;
+                    r[5] = ((t[3] >> 7) | (t[4] << 4)) as u8;
+                    r[6] = ((t[4] >> 4) | (t[5] << 7)) as u8;
+                    r[7] = (t[5] >> 1) as u8;
+                    r[8] = ((t[5] >> 9) | (t[6] << 2)) as u8;
+                    r[9] = ((t[6] >> 6) | (t[7] << 5)) as u8;
+                    r[10] = (t[7] >> 3) as u8;
+                }
+            }
+        } else {
+            let mut t = [0u16; 4];
+            for (poly, out) in self.vec.iter().zip(out.chunks_exact_mut(320)) {
+                for (coeffs, r) in poly.coeffs.chunks_exact(4).zip(out.chunks_exact_mut(5)) {
+                    for (t, &c) in t.iter_mut().zip(coeffs) {
+                        *t = compress_coeff(positive_repr(c), 10);
+                    }
+
+                    r[0] = (t[0] >> 0) as u8;
+                    r[1] = ((t[0] >> 8) | (t[1] << 2)) as u8;
+                    r[2] = ((t[1] >> 6) | (t[2] << 4)) as u8;
+                    r[3] = ((t[2] >> 4) | (t[3] << 6)) as u8;
+                    r[4] = (t[3] >> 2) as u8;
+                }
+            }
+        }
+    }
+
+    /// De-serialize and decompress a vector of polynomials
+    ///
+    /// **Approximate** inverse of `compress_into`
+    pub fn decompress(input: &Array<u8, P::PolyVecCompressedBytes>) -> Self {
+        const Q: u32 = KYBER_Q as u32;
+        let mut out = Self::new();
+
+        if P::DU == 11 {
+            let mut t = [0u16; 8];
+            for (poly, input) in out.vec.iter_mut().zip(input.chunks_exact(352)) {
+                for (coeffs, a) in poly.coeffs.chunks_exact_mut(8).zip(input.chunks_exact(11)) {
+                    let a: [u16; 11] = core::array::from_fn(|k| a[k] as u16);
+                    t[0] = (a[0] >> 0) | (a[1] << 8);
+                    t[1] = (a[1] >> 3) | (a[2] << 5);
+                    t[2] = (a[2] >> 6) | (a[3] << 2) | (a[4] << 10);
+                    t[3] = (a[4] >> 1) | (a[5] << 7);
+                    t[4] = (a[5] >> 4) | (a[6] << 4);
+                    t[5] = (a[6] >> 7) | (a[7] << 1) | (a[8] << 9);
+                    t[6] = (a[8] >> 2) | (a[9] << 6);
+                    t[7] = (a[9] >> 5) | (a[10] << 3);
+
+                    for (c, &t) in coeffs.iter_mut().zip(t.iter()) {
+                        *c = (((t & 0x7ff) as u32 * Q + 1024) >> 11) as i16;
+                    }
+                }
+            }
+        } else {
+            let mut t = [0u16; 4];
+            for (poly, input) in out.vec.iter_mut().zip(input.chunks_exact(320)) {
+                for (coeffs, a) in poly.coeffs.chunks_exact_mut(4).zip(input.chunks_exact(5)) {
+                    t[0] = (a[0] >> 0) as u16 | ((a[1] as u16) << 8);
+                    t[1] = (a[1] >> 2) as u16 | ((a[2] as u16) << 6);
+                    t[2] = (a[2] >> 4) as u16 | ((a[3] as u16) << 4);
+                    t[3] = (a[3] >> 6) as u16 | ((a[4] as u16) << 2);
+
+                    for (c, &t) in coeffs.iter_mut().zip(t.iter()) {
+                        *c = (((t & 0x3ff) as u32 * Q + 512) >> 10) as i16;
+                    }
+                }
+            }
         }
 
-        let vec = unsafe { MaybeUninit::array_assume_init(vec) };
-        PolyVec { vec }
+        out
     }
 
     pub fn ntt(&mut self) {
@@ -58,6 +146,31 @@ impl<const K: usize> PolyVec<K> {
     pub fn invntt(&mut self) {
         self.vec.iter_mut().for_each(|p| p.invntt());
     }
+
+    /// Barrett-reduce all coefficients of all polynomials
+    pub fn reduce(&mut self) {
+        self.vec.iter_mut().for_each(|p| p.reduce());
+    }
+
+    /// Multiply the elements of two vectors in the NTT domain and accumulate
+    /// the products into a single polynomial
+    pub fn basemul_acc_montgomery(&self, other: &Self) -> Poly<P> {
+        let mut out = Poly::zero();
+        for (a, b) in self.vec.iter().zip(other.vec.iter()) {
+            out += a.basemul_montgomery(b);
+        }
+        out.reduce();
+        out
+    }
+}
+
+impl<P: ParameterSet> AddAssign<&PolyVec<P>> for PolyVec<P> {
+    fn add_assign(&mut self, rhs: &Self) {
+        self.vec
+            .iter_mut()
+            .zip(rhs.vec.iter())
+            .for_each(|(dst, src)| *dst += src);
+    }
 }
 
 #[cfg(test)]
@@ -66,11 +179,35 @@ mod test {
 
     #[test]
     fn test_to_from_bytes() {
-        let pv = PolyVec::<3>::random();
-        let mut outbuf = [0u8; {3 * KYBER_POLYBYTES}];
+        let pv = PolyVec::<Params768>::random();
+        let mut outbuf = Array::default();
+
+        pv.to_bytes(&mut outbuf);
+        let pv2 = PolyVec::<Params768>::from_bytes(&outbuf);
+        assert_eq!(pv, pv2);
+    }
+
+    fn check_compress_decompress<P: ParameterSet>() {
+        let pv = PolyVec::<P>::random();
+        let mut outbuf = Array::default();
+        pv.compress_into(&mut outbuf);
+        let pv2 = PolyVec::<P>::decompress(&outbuf);
 
-        pv.to_bytes(&mut outbuf)    ;
-        let pv2 = PolyVec::<3>::from_bytes(&outbuf);
-        assert_eq!(pv, pv2); 
+        let bits = P::DU as u32;
+        let bound = (KYBER_Q as i16 + (1 << bits)) >> (bits + 1);
+        for (l, r) in pv.vec.iter().zip(pv2.vec.iter()) {
+            for (&l, &r) in l.coeffs.iter().zip(r.coeffs.iter()) {
+                let diff = (l - r).rem_euclid(KYBER_Q as i16);
+                let diff = diff.min(KYBER_Q as i16 - diff);
+                assert!(diff <= bound, "|{} - {}| = {} > {}", l, r, diff, bound);
+            }
+        }
+    }
+
+    #[test]
+    fn test_compress_decompress() {
+        check_compress_decompress::<Params512>();
+        check_compress_decompress::<Params768>();
+        check_compress_decompress::<Params1024>();
     }
 }
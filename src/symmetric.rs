@@ -1,17 +1,207 @@
+use aes::cipher::{KeyIvInit, StreamCipher};
+use aes::Aes256;
 use digest::{ExtendableOutput, Update, XofReader};
+use sha2::{Sha256, Sha512};
 use sha3::Digest;
 use sha3::{Sha3XofReader, Sha3_256, Sha3_512, Shake128, Shake256};
 
-use crate::params::KYBER_SYMBYTES;
+use crate::params::{KYBER_SSBYTES, KYBER_SYMBYTES};
+use crate::utils::wipe;
 
-pub fn hash_h(buffer: &[u8]) -> [u8; 32] {
-    let digest = Sha3_256::digest(buffer);
-    digest.as_slice().try_into().unwrap()
+type Aes256Ctr = ctr::Ctr32BE<Aes256>;
+
+/// The symmetric primitives a Kyber parameter set is instantiated with
+pub(crate) trait Symmetric {
+    type Xof: Xof;
+    type XofX4: XofX4;
+
+    /// Whether the four-way functions below are faster than four calls of
+    /// the single ones, so that generating keys in groups of four pays off
+    const FAST_X4: bool = false;
+
+    /// The hash function H
+    fn hash_h(buffer: &[u8]) -> [u8; 32];
+
+    /// The hash function G
+    fn hash_g(buffer: &[u8]) -> [u8; 64];
+
+    /// The extendable-output function used to sample the matrix A
+    fn xof(seed: &[u8; KYBER_SYMBYTES], x: u8, y: u8) -> Self::Xof;
+
+    /// The pseudo-random function used to sample noise
+    fn prf(key: &[u8; KYBER_SYMBYTES], nonce: u8, output: &mut [u8]);
+
+    /// Derive the shared secret from the pre-key and the hash of the ciphertext
+    fn kdf(input: &[u8]) -> [u8; KYBER_SSBYTES];
+
+    /// The XOF for four seeds and the same matrix indices
+    fn xof_x4(seeds: [&[u8; KYBER_SYMBYTES]; 4], x: u8, y: u8) -> Self::XofX4;
+
+    /// The PRF for four keys and the same nonce
+    fn prf_x4(keys: [&[u8; KYBER_SYMBYTES]; 4], nonce: u8, outputs: [&mut [u8]; 4]) {
+        for (key, output) in keys.into_iter().zip(outputs) {
+            Self::prf(key, nonce, output);
+        }
+    }
+
+    /// The hash function H on four inputs of the same length
+    fn hash_h_x4(buffers: [&[u8]; 4]) -> [[u8; 32]; 4] {
+        buffers.map(Self::hash_h)
+    }
+
+    /// The hash function G on four inputs of the same length
+    fn hash_g_x4(buffers: [&[u8]; 4]) -> [[u8; 64]; 4] {
+        buffers.map(Self::hash_g)
+    }
 }
 
-pub fn hash_g(buffer: &[u8]) -> [u8; 64] {
-    let digest = Sha3_512::digest(&buffer);
-    digest.as_slice().try_into().unwrap()
+pub(crate) trait Xof {
+    /// Squeeze out output
+    fn squeeze(&mut self, output: &mut [u8]);
+}
+
+pub(crate) trait XofX4 {
+    /// Squeeze the same amount of output out of each of the four instances
+    fn squeeze(&mut self, outputs: [&mut [u8]; 4]);
+}
+
+impl<X: Xof> XofX4 for [X; 4] {
+    fn squeeze(&mut self, outputs: [&mut [u8]; 4]) {
+        for (xof, output) in self.iter_mut().zip(outputs) {
+            xof.squeeze(output);
+        }
+    }
+}
+
+/// SHA-3 and SHAKE, as used by Kyber and ML-KEM
+pub(crate) struct Fips202;
+
+impl Symmetric for Fips202 {
+    type Xof = XofState;
+    type XofX4 = KeccakX4<SHAKE128_RATE>;
+
+    const FAST_X4: bool = true;
+
+    fn hash_h(buffer: &[u8]) -> [u8; 32] {
+        let digest = Sha3_256::digest(buffer);
+        digest[..].try_into().unwrap()
+    }
+
+    fn hash_g(buffer: &[u8]) -> [u8; 64] {
+        let digest = Sha3_512::digest(buffer);
+        digest[..].try_into().unwrap()
+    }
+
+    fn xof(seed: &[u8; KYBER_SYMBYTES], x: u8, y: u8) -> XofState {
+        let mut xof = XofState::new();
+        xof.absorb(seed, x, y);
+        xof
+    }
+
+    fn prf(key: &[u8; KYBER_SYMBYTES], nonce: u8, output: &mut [u8]) {
+        Shake256::default()
+            .chain(key)
+            .chain([nonce])
+            .finalize_xof()
+            .read(output);
+    }
+
+    fn kdf(input: &[u8]) -> [u8; KYBER_SSBYTES] {
+        let mut output = [0u8; KYBER_SSBYTES];
+        Shake256::default()
+            .chain(input)
+            .finalize_xof()
+            .read(&mut output);
+        output
+    }
+
+    fn xof_x4(seeds: [&[u8; KYBER_SYMBYTES]; 4], x: u8, y: u8) -> Self::XofX4 {
+        let inputs = seeds.map(|seed| {
+            let mut input = [0u8; KYBER_SYMBYTES + 2];
+            input[..KYBER_SYMBYTES].copy_from_slice(seed);
+            input[KYBER_SYMBYTES] = x;
+            input[KYBER_SYMBYTES + 1] = y;
+            input
+        });
+        KeccakX4::new(inputs.each_ref().map(|input| &input[..]), SHAKE_PAD)
+    }
+
+    fn prf_x4(keys: [&[u8; KYBER_SYMBYTES]; 4], nonce: u8, outputs: [&mut [u8]; 4]) {
+        let mut inputs = keys.map(|key| {
+            let mut input = [0u8; KYBER_SYMBYTES + 1];
+            input[..KYBER_SYMBYTES].copy_from_slice(key);
+            input[KYBER_SYMBYTES] = nonce;
+            input
+        });
+        KeccakX4::<SHAKE256_RATE>::new(inputs.each_ref().map(|input| &input[..]), SHAKE_PAD)
+            .squeeze(outputs);
+        inputs.iter_mut().for_each(|input| wipe(input));
+    }
+
+    fn hash_h_x4(buffers: [&[u8]; 4]) -> [[u8; 32]; 4] {
+        let mut outputs = [[0u8; 32]; 4];
+        KeccakX4::<SHA3_256_RATE>::new(buffers, SHA3_PAD)
+            .squeeze(outputs.each_mut().map(|output| &mut output[..]));
+        outputs
+    }
+
+    fn hash_g_x4(buffers: [&[u8]; 4]) -> [[u8; 64]; 4] {
+        let mut outputs = [[0u8; 64]; 4];
+        KeccakX4::<SHA3_512_RATE>::new(buffers, SHA3_PAD)
+            .squeeze(outputs.each_mut().map(|output| &mut output[..]));
+        outputs
+    }
+}
+
+/// SHA-2 and AES-256-CTR, as used by Kyber-90s
+pub(crate) struct Nineties;
+
+impl Symmetric for Nineties {
+    type Xof = AesXof;
+    type XofX4 = [AesXof; 4];
+
+    fn hash_h(buffer: &[u8]) -> [u8; 32] {
+        let digest = Sha256::digest(buffer);
+        digest[..].try_into().unwrap()
+    }
+
+    fn hash_g(buffer: &[u8]) -> [u8; 64] {
+        let digest = Sha512::digest(buffer);
+        digest[..].try_into().unwrap()
+    }
+
+    fn xof(seed: &[u8; KYBER_SYMBYTES], x: u8, y: u8) -> AesXof {
+        let mut iv = [0u8; 16];
+        iv[0] = x;
+        iv[1] = y;
+        AesXof(Aes256Ctr::new(seed.into(), &iv.into()))
+    }
+
+    fn prf(key: &[u8; KYBER_SYMBYTES], nonce: u8, output: &mut [u8]) {
+        let mut iv = [0u8; 16];
+        iv[0] = nonce;
+        output.fill(0);
+        Aes256Ctr::new(key.into(), &iv.into()).apply_keystream(output);
+    }
+
+    fn kdf(input: &[u8]) -> [u8; KYBER_SSBYTES] {
+        Self::hash_h(input)
+    }
+
+    fn xof_x4(seeds: [&[u8; KYBER_SYMBYTES]; 4], x: u8, y: u8) -> Self::XofX4 {
+        seeds.map(|seed| Self::xof(seed, x, y))
+    }
+}
+
+/// AES-256 in counter mode, keyed with the seed and with the matrix
+/// indices as nonce
+pub(crate) struct AesXof(Aes256Ctr);
+
+impl Xof for AesXof {
+    fn squeeze(&mut self, output: &mut [u8]) {
+        output.fill(0);
+        self.0.apply_keystream(output);
+    }
 }
 
 pub(crate) struct XofState(XofStateVariant);
@@ -43,13 +233,14 @@ impl XofState {
         let mut buf = [0u8; KYBER_SYMBYTES + 2];
         buf[..KYBER_SYMBYTES].copy_from_slice(&data[..]);
         buf[KYBER_SYMBYTES] = x;
-        buf[KYBER_SYMBYTES] = y;
+        buf[KYBER_SYMBYTES + 1] = y;
 
-        xof.update(&buf);
+        xof.update(buf);
     }
+}
 
-    /// Squeeze out output
-    pub fn squeeze(&mut self, output: &mut [u8]) {
+impl Xof for XofState {
+    fn squeeze(&mut self, output: &mut [u8]) {
         self.0.finalize();
         let xof = match &mut self.0 {
             XofStateVariant::Squeeze(xof) => xof,
@@ -60,26 +251,249 @@ impl XofState {
     }
 }
 
-pub fn prf(key: &[u8; KYBER_SYMBYTES], nonce: u8, output: &mut [u8]) {
+// Rates of the Keccak sponges in bytes
+const SHAKE128_RATE: usize = 168;
+const SHAKE256_RATE: usize = 136;
+const SHA3_256_RATE: usize = 136;
+const SHA3_512_RATE: usize = 72;
+
+// Domain separation bits of FIPS 202, followed by the first bit of the padding
+const SHAKE_PAD: u8 = 0x1f;
+const SHA3_PAD: u8 = 0x06;
+
+/// Four Keccak sponges that absorb and squeeze in lockstep
+///
+/// The sponges absorb inputs of the same length and are squeezed by the same
+/// amounts, so they share the bookkeeping of the position in the block. The
+/// permutations still run one after the other.
+pub(crate) struct KeccakX4<const RATE: usize> {
+    state: [[u64; 25]; 4],
+    // the current output block of each sponge, of which `pos` bytes are used
+    block: [[u8; RATE]; 4],
+    pos: usize,
+}
+
+impl<const RATE: usize> KeccakX4<RATE> {
+    /// Absorb one input per sponge and pad it, starting with the bits `pad`
+    fn new(inputs: [&[u8]; 4], pad: u8) -> Self {
+        let len = inputs[0].len();
+        debug_assert!(inputs.iter().all(|input| input.len() == len));
+
+        let mut sponge = KeccakX4 {
+            state: [[0; 25]; 4],
+            block: [[0; RATE]; 4],
+            pos: RATE,
+        };
+        let full = len - len % RATE;
+        for offset in (0..full).step_by(RATE) {
+            for (state, input) in sponge.state.iter_mut().zip(inputs) {
+                xor_block(state, &input[offset..offset + RATE]);
+            }
+            sponge.permute();
+        }
+        for ((state, block), input) in sponge.state.iter_mut().zip(&mut sponge.block).zip(inputs) {
+            let tail = &input[full..];
+            block[..tail.len()].copy_from_slice(tail);
+            block[tail.len()] = pad;
+            block[RATE - 1] |= 0x80;
+            xor_block(state, block);
+        }
+        sponge
+    }
+
+    fn permute(&mut self) {
+        for state in &mut self.state {
+            keccak::f1600(state);
+        }
+    }
+}
+
+impl<const RATE: usize> XofX4 for KeccakX4<RATE> {
+    fn squeeze(&mut self, mut outputs: [&mut [u8]; 4]) {
+        let len = outputs[0].len();
+        debug_assert!(outputs.iter().all(|output| output.len() == len));
+
+        let mut done = 0;
+        while done < len {
+            if self.pos == RATE {
+                self.permute();
+                for (block, state) in self.block.iter_mut().zip(&self.state) {
+                    for (bytes, word) in block.chunks_exact_mut(8).zip(state) {
+                        bytes.copy_from_slice(&word.to_le_bytes());
+                    }
+                }
+                self.pos = 0;
+            }
+            let n = (RATE - self.pos).min(len - done);
+            for (output, block) in outputs.iter_mut().zip(&self.block) {
+                output[done..done + n].copy_from_slice(&block[self.pos..self.pos + n]);
+            }
+            self.pos += n;
+            done += n;
+        }
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<const RATE: usize> Drop for KeccakX4<RATE> {
+    fn drop(&mut self) {
+        use zeroize::Zeroize;
+        self.state.zeroize();
+        self.block.zeroize();
+    }
+}
+
+fn xor_block(state: &mut [u64; 25], block: &[u8]) {
+    for (word, bytes) in state.iter_mut().zip(block.chunks_exact(8)) {
+        *word ^= u64::from_le_bytes(bytes.try_into().unwrap());
+    }
+}
+
+/// Derive the implicit rejection key from `z` and the ciphertext
+/// (the function J of FIPS 203)
+pub fn hash_j(z: &[u8; KYBER_SYMBYTES], ct: &[u8]) -> [u8; KYBER_SSBYTES] {
+    let mut output = [0u8; KYBER_SSBYTES];
     Shake256::default()
-        .chain(&key)
-        .chain(&[nonce])
+        .chain(z)
+        .chain(ct)
         .finalize_xof()
-        .read(output);
+        .read(&mut output);
+    output
 }
 
 #[cfg(test)]
-
 mod test {
-    use crate::utils::random_array;
-
     use super::*;
+    use std::{format, string::String};
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Run every primitive on the input 0, 1, ..., 31
+    ///
+    /// The XOF is called with x = 1, y = 2 and squeezed in two steps, and
+    /// the PRF with nonce 3. The expected values were computed with Python's
+    /// `hashlib` and AES-256-CTR from `cryptography`, with the nonce
+    /// layout of the Kyber-90s reference implementation.
+    fn primitives<S: Symmetric>() -> [String; 5] {
+        let seed: [u8; KYBER_SYMBYTES] = core::array::from_fn(|i| i as u8);
+        let mut xof = [0u8; 64];
+        let mut state = S::xof(&seed, 1, 2);
+        state.squeeze(&mut xof[..32]);
+        state.squeeze(&mut xof[32..]);
+        let mut prf = [0u8; 64];
+        S::prf(&seed, 3, &mut prf);
+        [
+            hex(&S::hash_h(&seed)),
+            hex(&S::hash_g(&seed)),
+            hex(&xof),
+            hex(&prf),
+            hex(&S::kdf(&seed)),
+        ]
+    }
+
+    #[test]
+    fn test_fips202() {
+        let [h, g, xof, prf, kdf] = primitives::<Fips202>();
+        assert_eq!(
+            h,
+            "050a48733bd5c2756ba95c5828cc83ee16fabcd3c086885b7744f84a0f9e0d94"
+        );
+        assert_eq!(
+            g,
+            "cbd3f6eeba676b21e0f2c47522292482fd830f330c1d84a794bb94728b2d93fe\
+             be4c18eae5a7e017e35fa090de24262e70951ad1d7dfb3a8c96d1134fb1879f2"
+        );
+        assert_eq!(
+            xof,
+            "02ae6624d5ce84cc5921c1fb933691fa170c0a22dc76343cccfdcb53c660234b\
+             329633510c998679299fafc6c81ec1d1679fc9721137996d4a0110a60bb4b02d"
+        );
+        assert_eq!(
+            prf,
+            "7aa03afaa1c5689320755b424e98cb1f290e04c92f28c4268bb29ebac2978a5a\
+             8bee7173e10cb5528bb1e5f53883556afb980f7035779743bac28bd69e6e149a"
+        );
+        assert_eq!(
+            kdf,
+            "69f07c8840ce80024db30939882c3d5bbc9c98b3e31e4513ebd2ca9b4503cdd3"
+        );
+    }
 
     #[test]
-    fn test_hashes() {
-        let data = random_array::<10>();
+    fn test_nineties() {
+        let [h, g, xof, prf, kdf] = primitives::<Nineties>();
+        assert_eq!(
+            h,
+            "630dcd2966c4336691125448bbb25b4ff412a49c732db2c8abc1b8581bd710dd"
+        );
+        assert_eq!(
+            g,
+            "3d94eea49c580aef816935762be049559d6d1440dede12e6a125f1841fff8e6f\
+             a9d71862a3e5746b571be3d187b0041046f52ebd850c7cbd5fde8ee38473b649"
+        );
+        assert_eq!(
+            xof,
+            "f79579553744ef11e12da2c6a978f28f482d81b5060723dc1d8fce9b223f6438\
+             0b8ddbe31a316567ea81c308fff90d6e4a5e7694b43923c38285d7534613dcb2"
+        );
+        assert_eq!(
+            prf,
+            "80c3017e8f89ab315ede32b11e48ab5012e8841c7fd1808b295ca82577b9d1bf\
+             908c5899595132d8f8388fa492d606c92fd3c064da9bd2e0cb97494f1e4bd012"
+        );
+        // Kyber-90s derives the shared secret with SHA-256
+        assert_eq!(kdf, h);
+    }
+
+    /// Check that the four-way primitives give the same results as the
+    /// one-way primitives
+    fn check_x4<S: Symmetric>() {
+        let seeds: [[u8; KYBER_SYMBYTES]; 4] = core::array::from_fn(|i| [i as u8; KYBER_SYMBYTES]);
+        let seeds = seeds.each_ref();
+
+        let mut xof = S::xof_x4(seeds, 1, 2);
+        let mut outputs = [[0u8; 3 * SHAKE128_RATE]; 4];
+        // squeeze across block boundaries
+        for range in [0..1, 1..200, 200..336, 336..3 * SHAKE128_RATE] {
+            xof.squeeze(outputs.each_mut().map(|output| &mut output[range.clone()]));
+        }
+        for (seed, output) in seeds.into_iter().zip(&outputs) {
+            let mut expected = [0u8; 3 * SHAKE128_RATE];
+            S::xof(seed, 1, 2).squeeze(&mut expected);
+            assert_eq!(output, &expected);
+        }
+
+        let mut outputs = [[0u8; 192]; 4];
+        S::prf_x4(seeds, 5, outputs.each_mut().map(|output| &mut output[..]));
+        for (seed, output) in seeds.into_iter().zip(&outputs) {
+            let mut expected = [0u8; 192];
+            S::prf(seed, 5, &mut expected);
+            assert_eq!(output, &expected);
+        }
 
-        hash_h(&data[..]);
-        hash_g(&data[..]);
+        let inputs: [[u8; 1600]; 4] =
+            core::array::from_fn(|i| core::array::from_fn(|j| (i * j) as u8));
+        for len in [0, 33, 71, 72, 135, 136, 137, 1184, 1600] {
+            let buffers = inputs.each_ref().map(|input| &input[..len]);
+            assert_eq!(S::hash_h_x4(buffers), buffers.map(S::hash_h));
+            assert_eq!(S::hash_g_x4(buffers), buffers.map(S::hash_g));
+        }
+    }
+
+    #[test]
+    fn test_x4() {
+        check_x4::<Fips202>();
+        check_x4::<Nineties>();
+    }
+
+    #[test]
+    fn test_hash_j() {
+        let z: [u8; KYBER_SYMBYTES] = core::array::from_fn(|i| i as u8);
+        assert_eq!(
+            hex(&hash_j(&z, &[0xff; 3])),
+            "c132ab45c4c0b8c45b16faaf609a2caa10adc95ebe564b5b8a63c9cede2f6801"
+        );
     }
 }
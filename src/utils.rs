@@ -1,18 +1,20 @@
 #[cfg(test)]
 use rand::prelude::*;
 
-// based on https://github.com/rust-lang/rust/pull/83233/files#diff-e8ccaf64ce21f955ccebef33b52158631493a6f0966815a2ebc142d7cd2b5e06R1671-R1677
+/// Split an array into a prefix of length `M` and the remainder
+///
+/// Panics if `M > N`, which the callers rule out with constant lengths.
 pub fn split_array<T, const N: usize, const M: usize>(arr: &[T; N]) -> (&[T; M], &[T]) {
     let (l, r) = arr.split_at(M);
-    unsafe { (&*(l.as_ptr() as *const [T; M]), r) }
+    (l.try_into().unwrap(), r)
 }
 
-// based on https://github.com/rust-lang/rust/pull/83233/files#diff-e8ccaf64ce21f955ccebef33b52158631493a6f0966815a2ebc142d7cd2b5e06R1671-R1677
-pub fn split_array_mut<T, const N: usize, const M: usize>(
-    arr: &mut [T; N],
-) -> (&mut [T; M], &mut [T]) {
-    let (l, r) = arr.split_at_mut(M);
-    unsafe { (&mut *(l.as_mut_ptr() as *mut [T; M]), r) }
+/// Overwrite a secret temporary with zeros if the `zeroize` feature is enabled
+pub(crate) fn wipe(secret: &mut [u8]) {
+    #[cfg(feature = "zeroize")]
+    zeroize::Zeroize::zeroize(secret);
+    #[cfg(not(feature = "zeroize"))]
+    let _ = secret;
 }
 
 #[cfg(test)]
@@ -0,0 +1,37 @@
+//! Constant-time comparison and conditional move
+
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq};
+
+/// Compare two byte strings of equal length in constant time
+pub(crate) fn ct_eq(a: &[u8], b: &[u8]) -> Choice {
+    debug_assert_eq!(a.len(), b.len());
+    a.ct_eq(b)
+}
+
+/// Copy `src` to `dst` if `condition` is set, in constant time
+pub(crate) fn cmov(dst: &mut [u8], src: &[u8], condition: Choice) {
+    debug_assert_eq!(dst.len(), src.len());
+    for (d, s) in dst.iter_mut().zip(src) {
+        d.conditional_assign(s, condition);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ct_eq() {
+        assert!(bool::from(ct_eq(&[1, 2, 3], &[1, 2, 3])));
+        assert!(!bool::from(ct_eq(&[1, 2, 3], &[1, 2, 4])));
+    }
+
+    #[test]
+    fn test_cmov() {
+        let mut dst = [1u8, 2, 3];
+        cmov(&mut dst, &[4, 5, 6], Choice::from(0));
+        assert_eq!(dst, [1, 2, 3]);
+        cmov(&mut dst, &[4, 5, 6], Choice::from(1));
+        assert_eq!(dst, [4, 5, 6]);
+    }
+}
@@ -0,0 +1,77 @@
+//! Check that no KEM operation allocates
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use kyber::kem::{dynamic, kyber1024_90s, kyber512, ml_kem_768};
+use rand::{rngs::StdRng, SeedableRng};
+
+/// Counts the allocations made by the current thread, so that tests
+/// running in parallel don't interfere
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Run `f` and return how many allocations it made
+fn count_allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+macro_rules! check_no_alloc {
+    ($name: ident, $module: ident) => {
+        #[test]
+        fn $name() {
+            let mut rng = StdRng::seed_from_u64(0);
+            let allocations = count_allocations(|| {
+                let (pk, sk) = $module::keypair(&mut rng).unwrap();
+                let (ct, ss) = $module::encaps(&pk, &mut rng).unwrap();
+                assert_eq!(ss.as_bytes(), $module::decaps(&ct, &sk).as_bytes());
+
+                let pk = $module::PublicKey::try_from_bytes(pk.as_bytes()).unwrap();
+                let sk = $module::SecretKey::try_from(&sk.as_bytes()[..]).unwrap();
+                let ct = $module::Ciphertext::try_from(&ct.as_bytes()[..]).unwrap();
+                assert_eq!(ss.as_bytes(), $module::decaps(&ct, &sk).as_bytes());
+                $module::pairwise_consistency_test(&pk, &sk, &mut rng).unwrap();
+
+                let mut keys: [Option<_>; 3] = [None, None, None];
+                $module::keypair_batch(&mut rng, &mut keys).unwrap();
+            });
+            assert_eq!(allocations, 0);
+        }
+    };
+}
+
+check_no_alloc!(test_kyber512_no_alloc, kyber512);
+check_no_alloc!(test_kyber1024_90s_no_alloc, kyber1024_90s);
+check_no_alloc!(test_ml_kem_768_no_alloc, ml_kem_768);
+
+#[test]
+fn test_dynamic_no_alloc() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let allocations = count_allocations(|| {
+        let (pk, sk) = dynamic::keypair(dynamic::KyberVariant::Kyber768, &mut rng).unwrap();
+        let (ct, ss) = dynamic::encaps(&pk, &mut rng).unwrap();
+        assert_eq!(ss.as_bytes(), dynamic::decaps(&ct, &sk).unwrap().as_bytes());
+    });
+    assert_eq!(allocations, 0);
+}